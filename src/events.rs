@@ -0,0 +1,76 @@
+//! NEP-297 events logged by the linkdrop, so indexers can follow the lifecycle of the drops.
+use near_sdk::json_types::{Base58PublicKey, U64};
+use near_sdk::{env, AccountId, PublicKey};
+use serde::Serialize;
+
+/// Name of the event standard.
+const EVENT_STANDARD: &str = "linkdrop";
+
+/// Version of the event schema. Bump it on any change of the event data.
+const EVENT_VERSION: &str = "1.1.0";
+
+/// Drop identified by its key, with the account that created it.
+#[derive(Serialize)]
+pub struct DropData {
+    pub public_key: Base58PublicKey,
+    pub sender_id: AccountId,
+}
+
+/// Use of a drop, with the number of uses it has left.
+#[derive(Serialize)]
+pub struct DropClaimedData {
+    pub public_key: Base58PublicKey,
+    pub sender_id: AccountId,
+    pub remaining_uses: U64,
+}
+
+#[derive(Serialize)]
+pub struct AccountCreatedData {
+    pub account_id: AccountId,
+}
+
+#[derive(Serialize)]
+pub struct ClaimFailedData {
+    pub public_key: Base58PublicKey,
+    pub reason: String,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum Event {
+    DropCreated(Vec<DropData>),
+    DropClaimed(Vec<DropClaimedData>),
+    AccountCreated(Vec<AccountCreatedData>),
+    DropCancelled(Vec<DropData>),
+    DropExpired(Vec<DropData>),
+    ClaimFailed(Vec<ClaimFailedData>),
+}
+
+#[derive(Serialize)]
+struct EventLog<'a> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+impl Event {
+    /// Logs the event as `EVENT_JSON:{"standard": "linkdrop", "version": ..., "event": ..., "data": [...]}`.
+    pub fn emit(&self) {
+        let log = EventLog {
+            standard: EVENT_STANDARD,
+            version: EVENT_VERSION,
+            event: self,
+        };
+        env::log(format!("EVENT_JSON:{}", serde_json::to_string(&log).unwrap()).as_bytes());
+    }
+}
+
+impl DropData {
+    pub fn new(public_key: &PublicKey, sender_id: &AccountId) -> Self {
+        Self {
+            public_key: Base58PublicKey(public_key.clone()),
+            sender_id: sender_id.clone(),
+        }
+    }
+}
@@ -1,11 +1,22 @@
 use borsh::{BorshDeserialize, BorshSerialize};
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_contract_standards::non_fungible_token::approval::NonFungibleTokenApprovalReceiver;
+use near_contract_standards::non_fungible_token::core::NonFungibleTokenReceiver;
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
 use near_contract_standards::non_fungible_token::TokenId;
-use near_sdk::collections::LookupMap;
+use near_sdk::collections::{LookupMap, LookupSet, UnorderedMap, UnorderedSet, Vector};
 use near_sdk::json_types::ValidAccountId;
-use near_sdk::json_types::{Base58PublicKey, U128};
+use near_sdk::json_types::{Base58PublicKey, Base64VecU8, U128, U64};
 use near_sdk::{
-    env, ext_contract, near_bindgen, AccountId, Gas, Promise, PromiseResult, PublicKey,
+    env, ext_contract, near_bindgen, AccountId, Balance, Gas, Promise, PromiseOrValue,
+    PromiseResult, PublicKey,
 };
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::events::{AccountCreatedData, ClaimFailedData, DropClaimedData, DropData, Event};
+
+mod events;
 
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
@@ -13,18 +24,484 @@ static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize)]
 pub struct LinkDrop {
-    // Lookup map that indicates which accounts are eligible to claim the nft
+    // Map that indicates which accounts are eligible to claim the nft
+    pub accounts: UnorderedMap<PublicKey, DropInfo>,
+    // NFT contracts the linkdrop accepts drops from
+    pub nft_contracts: UnorderedSet<AccountId>,
+    // Fungible token contracts the linkdrop accepts drops from
+    pub ft_contracts: UnorderedSet<AccountId>,
+    // Accounts besides the owner allowed to create drops of tokens held by the contract
+    pub senders: UnorderedSet<AccountId>,
+    // Drops registered before the contract supported several NFT contracts
+    pub legacy_accounts: LookupMap<PublicKey, TokenId>,
+    // NFT contract of the drops in `legacy_accounts`
+    pub legacy_nft_contract_id: AccountId,
+    // Keys of the drops in `accounts` of each sender
+    pub drops_per_sender: LookupMap<AccountId, UnorderedSet<PublicKey>>,
+    // Key of the drop holding each undelivered `(nft_contract_id, token_id)`
+    pub drop_by_token: LookupMap<(AccountId, TokenId), PublicKey>,
+    // Campaigns grouping drops, by campaign id
+    pub campaigns: UnorderedMap<String, Campaign>,
+    // Pools of tokens held by the contract that claims draw from, by pool id
+    pub pools: UnorderedMap<String, Pool>,
+    // `(nft_contract_id, token_id)` of the tokens of all pools that weren't drawn yet
+    pub pooled_tokens: LookupSet<(AccountId, TokenId)>,
+    // NEAR deposited by each account to pay the allowance of drops it creates with transfer calls
+    pub allowance_deposits: LookupMap<AccountId, Balance>,
+}
+
+/// Token to be claimed with a linkdrop key.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug, PartialEq)]
+pub enum Asset {
+    Nft {
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        // Approval of the linkdrop when the token stays with its owner until the claim
+        approval_id: Option<u64>,
+    },
+    Ft {
+        ft_contract_id: AccountId,
+        amount: Balance,
+    },
+    Near {
+        amount: Balance,
+    },
+    // Token drawn from the pool of the drop on claim
+    Pool,
+    // Token minted on claim, with the storage deposit attached to `nft_mint`
+    Mint {
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        token_metadata: Box<TokenMetadata>,
+        deposit: Balance,
+    },
+}
+
+impl std::fmt::Display for Asset {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Asset::Nft {
+                nft_contract_id,
+                token_id,
+                ..
+            } => write!(f, "token {} of {}", token_id, nft_contract_id),
+            Asset::Ft {
+                ft_contract_id,
+                amount,
+            } => write!(f, "{} of {}", amount, ft_contract_id),
+            Asset::Near { amount } => write!(f, "{} yoctoNEAR", amount),
+            Asset::Pool => write!(f, "token of the pool"),
+            Asset::Mint {
+                nft_contract_id,
+                token_id,
+                ..
+            } => write!(f, "new token {} of {}", token_id, nft_contract_id),
+        }
+    }
+}
+
+/// Claim state of a drop.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DropStatus {
+    // The drop can be claimed with its key
+    Available,
+    // A claim is waiting for its transfers to finish
+    Claiming,
+    // Every asset was received by the claimer and the key was deleted
+    Claimed,
+    // Some assets couldn't be transferred to the account created by the claim
+    Failed { reason: String },
+}
+
+/// Drop that can be claimed with a linkdrop key.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug, PartialEq)]
+pub struct DropInfo {
+    pub assets: Vec<Asset>,
+    // Whether each asset of `assets` has already been received by the claimer
+    pub claimed: Vec<bool>,
+    // Account that created the drop
+    pub sender_id: AccountId,
+    // Block timestamp in nanoseconds after which the drop can't be claimed anymore
+    pub expires_at: Option<u64>,
+    // Whether the contract added the access key of the drop, see `on_keys_added`. Keys that
+    // weren't added by the contract for the drop are never deleted.
+    pub key_added: bool,
+    pub status: DropStatus,
+    // Account created by `create_account_and_claim` for the current or failed claim
+    pub new_account_id: Option<AccountId>,
+    pub campaign_id: Option<String>,
+    // Memo template of the NFT transfers of the claim, see `claim_memo`
+    pub memo: Option<String>,
+    // Number of successful claims left before the key is deleted
+    pub remaining_uses: u64,
+    // Pool the `Asset::Pool` assets of the drop are drawn from
+    pub pool_id: Option<String>,
+    // Hash of the password claims must pass, see `hash_password`
+    pub password_hash: Option<Vec<u8>>,
+    // Accounts the claims can deliver to, any account if not set
+    pub allowed_receivers: Option<AllowedReceivers>,
+}
+
+impl DropInfo {
+    pub fn new(assets: Vec<Asset>, sender_id: AccountId, expires_at: Option<u64>) -> Self {
+        Self {
+            claimed: vec![false; assets.len()],
+            assets,
+            sender_id,
+            expires_at,
+            key_added: false,
+            status: DropStatus::Available,
+            new_account_id: None,
+            campaign_id: None,
+            memo: None,
+            remaining_uses: 1,
+            pool_id: None,
+            password_hash: None,
+            allowed_receivers: None,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| env::block_timestamp() >= expires_at)
+    }
+
+    /// `(nft_contract_id, token_id)` of the NFTs that the claimer didn't receive yet.
+    pub fn unclaimed_tokens(&self) -> impl Iterator<Item = (AccountId, TokenId)> + '_ {
+        self.unclaimed_assets()
+            .filter_map(|(_, asset)| match asset {
+                Asset::Nft {
+                    nft_contract_id,
+                    token_id,
+                    ..
+                } => Some((nft_contract_id.clone(), token_id.clone())),
+                _ => None,
+            })
+    }
+
+    /// Assets transferred by the next claim: the next unclaimed asset while the drop has
+    /// several uses left, and every unclaimed asset on its last use.
+    pub fn next_claim_assets(&self) -> impl Iterator<Item = (usize, &Asset)> + '_ {
+        let count = if self.remaining_uses > 1 {
+            1
+        } else {
+            self.assets.len()
+        };
+        self.unclaimed_assets().take(count)
+    }
+
+    /// Assets that the claimer didn't receive yet, with their index in `assets`.
+    pub fn unclaimed_assets(&self) -> impl Iterator<Item = (usize, &Asset)> + '_ {
+        self.assets
+            .iter()
+            .enumerate()
+            .filter(move |(index, _)| !self.claimed[*index])
+    }
+}
+
+/// Accounts a drop can be claimed to.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AllowedReceivers {
+    Accounts(Vec<AccountId>),
+    // The account of the suffix and its sub-accounts, e.g. `.near` or `alice.near`
+    Suffix(String),
+}
+
+impl AllowedReceivers {
+    pub fn allows(&self, account_id: &AccountId) -> bool {
+        match self {
+            AllowedReceivers::Accounts(account_ids) => account_ids.contains(account_id),
+            AllowedReceivers::Suffix(suffix) => {
+                let suffix = suffix.trim_start_matches('.');
+                account_id == suffix || account_id.ends_with(&format!(".{}", suffix))
+            }
+        }
+    }
+}
+
+/// Group of drops of an NFT contract sharing the same settings.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Campaign {
+    // Account allowed to send drops of the campaign besides the contract owner
+    pub owner_id: AccountId,
+    pub nft_contract_id: AccountId,
+    // Block timestamp in nanoseconds after which the drops of the campaign can't be claimed
+    pub expires_at: Option<u64>,
+    // Maximum number of drops of the campaign that can be claimed
+    pub max_claims: Option<u64>,
+    // Memo passed to the NFT contract when transferring the tokens of the campaign
+    pub memo: Option<String>,
+    pub metadata: Option<String>,
+    // Number of drops created, claimed and cancelled or reclaimed
+    pub created: u64,
+    pub claimed: u64,
+    pub removed: u64,
+    // Number of claims in progress, which count against `max_claims` until they finish
+    pub claiming: u64,
+}
+
+/// Settings of a campaign passed to `create_campaign`.
+#[derive(Serialize, Deserialize)]
+pub struct CampaignArgs {
+    pub owner_id: ValidAccountId,
+    pub nft_contract_id: ValidAccountId,
+    pub expires_at: Option<U64>,
+    pub max_claims: Option<U64>,
+    pub memo: Option<String>,
+    pub metadata: Option<String>,
+}
+
+/// Campaign as returned by the view methods.
+#[derive(Serialize)]
+pub struct CampaignView {
+    pub campaign_id: String,
+    pub owner_id: AccountId,
+    pub nft_contract_id: AccountId,
+    pub expires_at: Option<U64>,
+    pub max_claims: Option<U64>,
+    pub memo: Option<String>,
+    pub metadata: Option<String>,
+    pub created: U64,
+    pub claimed: U64,
+    // Drops neither claimed, cancelled nor reclaimed
+    pub outstanding: U64,
+}
+
+impl CampaignView {
+    fn new(campaign_id: String, campaign: Campaign) -> Self {
+        Self {
+            campaign_id,
+            owner_id: campaign.owner_id,
+            nft_contract_id: campaign.nft_contract_id,
+            expires_at: campaign.expires_at.map(U64),
+            max_claims: campaign.max_claims.map(U64),
+            memo: campaign.memo,
+            metadata: campaign.metadata,
+            created: U64(campaign.created),
+            claimed: U64(campaign.claimed),
+            outstanding: U64(campaign.created - campaign.claimed - campaign.removed),
+        }
+    }
+}
+
+/// Tokens of an NFT contract held by the contract that claims of the drops of the pool draw from.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Pool {
+    pub nft_contract_id: AccountId,
+    // Tokens added to the pool, the ones from `next_index` on are still available
+    pub tokens: Vector<TokenId>,
+    pub next_index: u64,
+    // Whether claims draw a random available token instead of the next one
+    pub random: bool,
+}
+
+impl Pool {
+    /// Takes the next available token, or a random one if the pool is random.
+    pub fn draw(&mut self) -> Option<TokenId> {
+        let available = self.tokens.len() - self.next_index;
+        if available == 0 {
+            return None;
+        }
+        let mut index = self.next_index;
+        if self.random {
+            let seed = env::random_seed().iter().fold(0u64, |seed, byte| {
+                seed.wrapping_mul(256).wrapping_add(u64::from(*byte))
+            });
+            index += seed % available;
+        }
+        let next_token_id = self.tokens.get(self.next_index).unwrap();
+        let token_id = self.tokens.replace(index, &next_token_id);
+        self.next_index += 1;
+        Some(token_id)
+    }
+}
+
+/// Pool as returned by the view methods.
+#[derive(Serialize)]
+pub struct PoolView {
+    pub pool_id: String,
+    pub nft_contract_id: AccountId,
+    pub random: bool,
+    // Tokens not drawn yet
+    pub available: U64,
+}
+
+impl PoolView {
+    fn new(pool_id: String, pool: Pool) -> Self {
+        Self {
+            pool_id,
+            available: U64(pool.tokens.len() - pool.next_index),
+            nft_contract_id: pool.nft_contract_id,
+            random: pool.random,
+        }
+    }
+}
+
+/// Asset of a bundle passed to `send_bundle`. The contract must already hold the tokens.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetArgs {
+    Nft {
+        nft_contract_id: ValidAccountId,
+        token_id: TokenId,
+    },
+    Ft {
+        ft_contract_id: ValidAccountId,
+        amount: U128,
+    },
+}
+
+/// State layout of the contract when it only supported a single NFT contract.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct OldLinkDrop {
     pub accounts: LookupMap<PublicKey, TokenId>,
-    // Stores the nft info to be claimed
     pub nft_contract_id: AccountId,
 }
 
+/// Asset of a drop as returned by the view methods.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetView {
+    Nft {
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+    },
+    Ft {
+        ft_contract_id: AccountId,
+        amount: U128,
+    },
+    Near {
+        amount: U128,
+    },
+    Pool,
+    Mint {
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        token_metadata: Box<TokenMetadata>,
+    },
+}
+
+impl From<Asset> for AssetView {
+    fn from(asset: Asset) -> Self {
+        match asset {
+            Asset::Nft {
+                nft_contract_id,
+                token_id,
+                approval_id,
+            } => AssetView::Nft {
+                nft_contract_id,
+                token_id,
+                approval_id,
+            },
+            Asset::Ft {
+                ft_contract_id,
+                amount,
+            } => AssetView::Ft {
+                ft_contract_id,
+                amount: amount.into(),
+            },
+            Asset::Near { amount } => AssetView::Near {
+                amount: amount.into(),
+            },
+            Asset::Pool => AssetView::Pool,
+            Asset::Mint {
+                nft_contract_id,
+                token_id,
+                token_metadata,
+                ..
+            } => AssetView::Mint {
+                nft_contract_id,
+                token_id,
+                token_metadata,
+            },
+        }
+    }
+}
+
+/// Drop as returned by the view methods.
+#[derive(Serialize)]
+pub struct DropView {
+    pub public_key: Base58PublicKey,
+    pub assets: Vec<AssetView>,
+    pub claimed: Vec<bool>,
+    pub sender_id: AccountId,
+    pub status: DropStatus,
+    pub expires_at: Option<U64>,
+    pub campaign_id: Option<String>,
+    pub memo: Option<String>,
+    pub remaining_uses: U64,
+    pub pool_id: Option<String>,
+    pub password_protected: bool,
+    pub allowed_receivers: Option<AllowedReceivers>,
+}
+
+impl DropView {
+    fn new(public_key: PublicKey, drop: DropInfo) -> Self {
+        Self {
+            public_key: Base58PublicKey(public_key),
+            assets: drop.assets.into_iter().map(AssetView::from).collect(),
+            claimed: drop.claimed,
+            sender_id: drop.sender_id,
+            status: drop.status,
+            expires_at: drop.expires_at.map(U64),
+            campaign_id: drop.campaign_id,
+            memo: drop.memo,
+            remaining_uses: U64(drop.remaining_uses),
+            pool_id: drop.pool_id,
+            password_protected: drop.password_hash.is_some(),
+            allowed_receivers: drop.allowed_receivers,
+        }
+    }
+}
+
+/// Step of `create_account_and_claim` that failed.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClaimStep {
+    CreateAccount,
+}
+
+/// Result of the account creation of `create_account_and_claim`.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ClaimResult {
+    // Step that failed, `None` if the account was created and the transfers started
+    pub failed_step: Option<ClaimStep>,
+    // Deposit returned to the payer
+    pub refund: U128,
+}
+
 /// Access key allowance for linkdrop keys.
 const ACCESS_KEY_ALLOWANCE: u128 = 1_000_000_000_000_000_000_000_000;
 
 /// Gas to spend for nft transaction
 const TRANSFER_FROM_GAS: Gas = 10_000_000_000_000;
 
+/// Gas to spend for minting a token of a lazy-mint drop with `nft_mint`
+const NFT_MINT_GAS: Gas = 20_000_000_000_000;
+
+/// Deposit attached to `nft_mint` for the storage of each minted token.
+/// The token contract refunds the part it doesn't need.
+const MINT_STORAGE_DEPOSIT: u128 = 10_000_000_000_000_000_000_000;
+
+/// Gas to spend for looking up a token with `nft_token`
+const NFT_TOKEN_GAS: Gas = 10_000_000_000_000;
+
+/// Gas attached to the callback that registers a drop once its token is verified.
+const ON_NFT_TOKEN_CALLBACK_GAS: Gas = 40_000_000_000_000;
+
+/// Gas attached to the callback that marks the access keys of new drops as added.
+const ON_KEYS_ADDED_CALLBACK_GAS: Gas = 10_000_000_000_000;
+
+/// Gas to spend for registering the receiver on a fungible token contract
+const STORAGE_DEPOSIT_GAS: Gas = 10_000_000_000_000;
+
+/// Deposit attached to register the receiver on a fungible token contract.
+/// The token contract refunds the part it doesn't need.
+const FT_STORAGE_DEPOSIT: u128 = 12_500_000_000_000_000_000_000;
+
 /// Gas attached to the callback from account creation.
 pub const ON_CREATE_ACCOUNT_CALLBACK_GAS: u64 = 10_000_000_000_000;
 
@@ -33,27 +510,125 @@ const CREATE_SUBACCOUNT_ALLOWANCE: u128 = 1_000_000_000_000_000_000_000;
 /// Indicates there are no deposit for a callback for better readability.
 const NO_DEPOSIT: u128 = 0;
 
+/// Methods the linkdrop access keys are allowed to call.
+const LINKDROP_METHODS: &[u8] = b"claim,create_account_and_claim";
+
+/// Message expected by `nft_on_transfer` and `ft_on_transfer` to register a drop for the transferred tokens.
+#[derive(Deserialize)]
+pub struct DropMsg {
+    pub public_key: Base58PublicKey,
+    // Block timestamp in nanoseconds after which the drop can be reclaimed by the sender
+    pub expires_at: Option<U64>,
+    // Memo template of the NFT transfers of the claim
+    pub memo: Option<String>,
+    // Hash of the password claims must pass
+    pub password_hash: Option<Base64VecU8>,
+}
+
+/// Drop of `send` waiting for `nft_token` to verify its token.
+#[derive(Serialize, Deserialize)]
+pub struct SentDrop {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub sender_id: AccountId,
+    pub deposit: U128,
+    pub expires_at: Option<U64>,
+    pub campaign_id: Option<String>,
+    pub memo: Option<String>,
+    pub password_hash: Option<Base64VecU8>,
+    pub allowed_receivers: Option<AllowedReceivers>,
+}
+
+impl From<SentDrop> for DropInfo {
+    fn from(drop: SentDrop) -> Self {
+        DropInfo {
+            campaign_id: drop.campaign_id,
+            memo: drop.memo,
+            password_hash: drop.password_hash.map(|hash| hash.0),
+            allowed_receivers: drop.allowed_receivers,
+            ..DropInfo::new(
+                vec![Asset::Nft {
+                    nft_contract_id: drop.nft_contract_id,
+                    token_id: drop.token_id,
+                    approval_id: None,
+                }],
+                drop.sender_id,
+                drop.expires_at.map(|expires_at| expires_at.0),
+            )
+        }
+    }
+}
+
+/// Fields of the token returned by `nft_token` that `send` checks.
+#[derive(Deserialize)]
+pub struct NftToken {
+    pub owner_id: AccountId,
+    pub approved_account_ids: Option<HashMap<AccountId, u64>>,
+}
+
 #[ext_contract(ext_nft)]
 pub trait ExtNFTContract {
+    fn nft_token(&self, token_id: TokenId) -> Option<NftToken>;
+
     fn nft_transfer(
         &mut self,
-        receiver_id: ValidAccountId,
+        receiver_id: AccountId,
         token_id: TokenId,
         approval_id: Option<u64>,
         memo: Option<String>,
     ) -> Promise;
+
+    fn nft_mint(
+        &mut self,
+        token_id: TokenId,
+        receiver_id: AccountId,
+        token_metadata: TokenMetadata,
+    ) -> Promise;
+}
+
+#[ext_contract(ext_ft)]
+pub trait ExtFTContract {
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> Promise;
+
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
 }
 
 #[ext_contract(ext_self)]
 pub trait ExtLinkDrop {
     /// Callback after plain account creation.
-    fn on_account_created(&mut self, predecessor_account_id: AccountId, amount: U128) -> bool;
+    fn on_account_created(
+        &mut self,
+        new_account_id: AccountId,
+        predecessor_account_id: AccountId,
+        amount: U128,
+    ) -> bool;
 
-    /// Callback after creating account and claiming linkdrop.
-    fn on_account_created_and_claimed(&mut self) -> bool;
+    /// Callback after creating the account of `create_account_and_claim`.
+    fn on_account_created_and_claimed(
+        &mut self,
+        public_key: PublicKey,
+        new_account_id: AccountId,
+        predecessor_account_id: AccountId,
+        amount: U128,
+    ) -> ClaimResult;
+
+    /// Callback after looking up the token of a drop created with `send`.
+    fn on_send_nft_token(&mut self, public_key: PublicKey, drop: SentDrop) -> bool;
 
-    /// Callback to update the nft_contract_id
-    fn update_nft_storage(&mut self, public_key: PublicKey);
+    /// Callback after adding the access keys of new drops.
+    fn on_keys_added(&mut self, public_keys: Vec<PublicKey>) -> bool;
+
+    /// Callback after transferring an asset of a drop
+    fn update_nft_storage(
+        &mut self,
+        public_key: PublicKey,
+        asset_index: u64,
+        last_asset: bool,
+    ) -> bool;
 }
 
 fn is_promise_success() -> bool {
@@ -62,18 +637,25 @@ fn is_promise_success() -> bool {
         1,
         "Contract expected a result on the callback"
     );
-    match env::promise_result(0) {
-        PromiseResult::Successful(_) => true,
-        _ => false,
-    }
+    matches!(env::promise_result(0), PromiseResult::Successful(_))
 }
 
 impl Default for LinkDrop {
     fn default() -> Self {
-        let empty_set: LookupMap<PublicKey, TokenId> = LookupMap::new(0);
+        let empty_set: UnorderedMap<PublicKey, DropInfo> = UnorderedMap::new(1);
         Self {
             accounts: empty_set,
-            nft_contract_id: String::from(""),
+            nft_contracts: UnorderedSet::new(2),
+            ft_contracts: UnorderedSet::new(3),
+            senders: UnorderedSet::new(4),
+            legacy_accounts: LookupMap::new(0),
+            legacy_nft_contract_id: String::from(""),
+            drops_per_sender: LookupMap::new(5),
+            drop_by_token: LookupMap::new(7),
+            campaigns: UnorderedMap::new(8),
+            pools: UnorderedMap::new(9),
+            allowance_deposits: LookupMap::new(11),
+            pooled_tokens: LookupSet::new(12),
         }
     }
 }
@@ -82,491 +664,5385 @@ impl Default for LinkDrop {
 impl LinkDrop {
     #[init]
     pub fn init(nft_contract_id: AccountId) -> Self {
-        Self {
-            accounts: LookupMap::new(0),
-            nft_contract_id: nft_contract_id,
-        }
+        let mut this = Self::default();
+        this.nft_contracts.insert(&nft_contract_id);
+        this
     }
 
-    /// Allows given public key to claim sent balance.
-    /// Takes ACCESS_KEY_ALLOWANCE as fee from deposit to cover account creation via an access key.
-    #[payable]
-    pub fn send(&mut self, public_key: Base58PublicKey, token_id: TokenId) -> Promise {
+    /// Migrates the state of a contract deployed with a single `nft_contract_id`.
+    /// Existing drops stay claimable from that contract, which is also added to the whitelist.
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
         assert_eq!(
             env::signer_account_id(),
             env::current_account_id(),
-            "Only the Smart Contract owner can call send method"
-        );
-        assert!(
-            env::attached_deposit() > ACCESS_KEY_ALLOWANCE,
-            "Attached deposit must be greater than ACCESS_KEY_ALLOWANCE"
+            "Only the Smart Contract owner can call migrate method"
         );
-        let pk = public_key.into();
-        let new_account = self.accounts.insert(&pk, &token_id);
-        // If the set did not have this value present, None is returned
-        assert_eq!(new_account, None, "Account already registered");
-        Promise::new(env::current_account_id()).add_access_key(
-            pk,
-            env::attached_deposit(),
-            env::current_account_id(),
-            // add_access_key allows given pk to call functions claim or create_account_and_claim
-            b"claim,create_account_and_claim".to_vec(),
-        )
+        let old_state: OldLinkDrop = env::state_read().expect("Old state doesn't exist");
+        let mut this = Self {
+            legacy_accounts: old_state.accounts,
+            legacy_nft_contract_id: old_state.nft_contract_id,
+            ..Self::default()
+        };
+        this.nft_contracts.insert(&this.legacy_nft_contract_id);
+        this
     }
 
-    /// Claim tokens for specific account that are attached to the public key this tx is signed with.
-    pub fn claim(&mut self, account_id: ValidAccountId) -> Promise {
-        let token_id = self.accounts.get(&env::signer_account_pk());
-        assert_ne!(token_id, None, "Signer must be eligible to claim the NFT");
-        Promise::new(env::current_account_id()).then(
-            ext_nft::nft_transfer(
-                account_id.clone(),
-                token_id.unwrap(),
-                None,
-                None, //Memo
-                &self.nft_contract_id.clone(),
-                1,
-                TRANSFER_FROM_GAS,
-            )
-            .then(ext_self::update_nft_storage(
-                env::signer_account_pk(),
-                &env::current_account_id(),
-                NO_DEPOSIT,
-                TRANSFER_FROM_GAS,
-            )),
-        )
+    /// Allows drops from the given NFT contract.
+    pub fn add_nft_contract(&mut self, nft_contract_id: ValidAccountId) -> bool {
+        Self::assert_owner();
+        self.nft_contracts.insert(&nft_contract_id.into())
     }
 
-    /// Create new account and and claim tokens to it.
+    /// Stops accepting new drops from the given NFT contract. Pending drops stay claimable.
+    pub fn remove_nft_contract(&mut self, nft_contract_id: ValidAccountId) -> bool {
+        Self::assert_owner();
+        self.nft_contracts.remove(&nft_contract_id.into())
+    }
+
+    /// Allows drops from the given fungible token contract.
+    pub fn add_ft_contract(&mut self, ft_contract_id: ValidAccountId) -> bool {
+        Self::assert_owner();
+        self.ft_contracts.insert(&ft_contract_id.into())
+    }
+
+    /// Stops accepting new drops from the given fungible token contract. Pending drops stay claimable.
+    pub fn remove_ft_contract(&mut self, ft_contract_id: ValidAccountId) -> bool {
+        Self::assert_owner();
+        self.ft_contracts.remove(&ft_contract_id.into())
+    }
+
+    /// Allows the given account to create drops with `send`.
+    pub fn add_sender(&mut self, account_id: ValidAccountId) -> bool {
+        Self::assert_owner();
+        self.senders.insert(&account_id.into())
+    }
+
+    /// Removes the given account from the senders. Its pending drops stay claimable.
+    pub fn remove_sender(&mut self, account_id: ValidAccountId) -> bool {
+        Self::assert_owner();
+        self.senders.remove(&account_id.into())
+    }
+
+    /// Deposits the attached NEAR to pay the allowance of the drops the caller creates with
+    /// `nft_transfer_call` or `ft_transfer_call`, which take ACCESS_KEY_ALLOWANCE from the deposit
+    /// for each drop.
     #[payable]
-    pub fn create_account_and_claim(
-        &mut self,
-        new_account_id: ValidAccountId,
-        new_public_key: Base58PublicKey,
-    ) -> Promise {
+    pub fn deposit_allowance(&mut self) -> U128 {
+        let account_id = env::predecessor_account_id();
+        let balance =
+            self.allowance_deposits.get(&account_id).unwrap_or(0) + env::attached_deposit();
+        self.allowance_deposits.insert(&account_id, &balance);
+        balance.into()
+    }
+
+    /// Returns the caller's unused allowance deposit.
+    pub fn withdraw_allowance(&mut self) -> Promise {
+        let account_id = env::predecessor_account_id();
+        let balance = self
+            .allowance_deposits
+            .remove(&account_id)
+            .expect("No allowance deposit");
+        Promise::new(account_id).transfer(balance)
+    }
+
+    /// Returns the unused allowance deposit of the given account.
+    pub fn get_allowance_deposit(&self, account_id: ValidAccountId) -> U128 {
+        self.allowance_deposits
+            .get(&account_id.into())
+            .unwrap_or(0)
+            .into()
+    }
+
+    /// Creates a campaign that groups the drops sent with its `campaign_id`.
+    pub fn create_campaign(&mut self, campaign_id: String, campaign: CampaignArgs) {
+        Self::assert_owner();
         assert!(
-            env::attached_deposit() > CREATE_SUBACCOUNT_ALLOWANCE,
-            "Attached deposit must be greater than ACCESS_KEY_ALLOWANCE"
+            self.campaigns.get(&campaign_id).is_none(),
+            "Campaign already exists"
+        );
+        let nft_contract_id: AccountId = campaign.nft_contract_id.into();
+        assert!(
+            self.nft_contracts.contains(&nft_contract_id),
+            "NFT contract is not whitelisted"
+        );
+        self.campaigns.insert(
+            &campaign_id,
+            &Campaign {
+                owner_id: campaign.owner_id.into(),
+                nft_contract_id,
+                expires_at: campaign.expires_at.map(|expires_at| expires_at.0),
+                max_claims: campaign.max_claims.map(|max_claims| max_claims.0),
+                memo: campaign.memo,
+                metadata: campaign.metadata,
+                created: 0,
+                claimed: 0,
+                removed: 0,
+                claiming: 0,
+            },
         );
-        let token_id = self.accounts.get(&env::signer_account_pk());
-        // Check if pk is in accounts lookupmap
-        assert_ne!(token_id, None, "Signer must be eligible to claim the NFT");
-        Promise::new(new_account_id.to_string())
-            .create_account()
-            .add_full_access_key(new_public_key.clone().into())
-            .transfer(env::attached_deposit())
-            .then(ext_nft::nft_transfer(
-                new_account_id,
-                token_id.unwrap(),
-                None,
-                None,
-                &self.nft_contract_id,
-                1,
-                TRANSFER_FROM_GAS,
-            ))
-            .then(ext_self::update_nft_storage(
-                new_public_key.into(),
-                &env::current_account_id(),
-                NO_DEPOSIT,
-                TRANSFER_FROM_GAS,
-            ))
     }
 
-    /// Create new account without linkdrop and deposit passed funds (used for creating sub accounts directly).
+    /// Creates an empty pool of tokens of `nft_contract_id`. Claims draw the tokens in the order
+    /// they were added, or randomly if `random` is set.
+    pub fn create_pool(&mut self, pool_id: String, nft_contract_id: ValidAccountId, random: bool) {
+        Self::assert_owner();
+        assert!(self.pools.get(&pool_id).is_none(), "Pool already exists");
+        let nft_contract_id: AccountId = nft_contract_id.into();
+        assert!(
+            self.nft_contracts.contains(&nft_contract_id),
+            "NFT contract is not whitelisted"
+        );
+        let mut prefix = vec![10];
+        prefix.extend(env::sha256(pool_id.as_bytes()));
+        self.pools.insert(
+            &pool_id,
+            &Pool {
+                nft_contract_id,
+                tokens: Vector::new(prefix),
+                next_index: 0,
+                random,
+            },
+        );
+    }
+
+    /// Adds tokens held by the contract to a pool. Panics if a token is already in a pool,
+    /// repeated or in a drop.
+    pub fn add_pool_tokens(&mut self, pool_id: String, token_ids: Vec<TokenId>) {
+        Self::assert_owner();
+        let mut pool = self.pools.get(&pool_id).expect("Pool not found");
+        for token_id in token_ids {
+            let token = (pool.nft_contract_id.clone(), token_id.clone());
+            assert!(
+                self.pooled_tokens.insert(&token),
+                "Token is already in a pool"
+            );
+            assert!(
+                !self.drop_by_token.contains_key(&token),
+                "Token is already in a drop"
+            );
+            pool.tokens.push(&token_id);
+        }
+        self.pools.insert(&pool_id, &pool);
+    }
+
+    /// Allows given public key to claim sent balance.
+    /// Takes ACCESS_KEY_ALLOWANCE as fee from deposit to cover account creation via an access key.
+    /// The drop is registered once `nft_token` confirms the linkdrop owns or is approved for
+    /// the token. Otherwise the deposit is refunded.
+    /// Drops of a campaign can be sent by its owner, use the campaign's NFT contract and expire
+    /// with the campaign at the latest.
+    /// `memo` is passed to `nft_transfer` on claim instead of the campaign's memo, see `claim_memo`.
+    /// Claims of drops with a `password_hash` must pass the password, see `hash_password`.
+    /// Claims of drops with `allowed_receivers` can only deliver to those accounts.
     #[payable]
-    pub fn create_account(
+    #[allow(clippy::too_many_arguments)]
+    pub fn send(
         &mut self,
-        new_account_id: ValidAccountId,
-        new_public_key: Base58PublicKey,
+        public_key: Base58PublicKey,
+        nft_contract_id: ValidAccountId,
+        token_id: TokenId,
+        expires_at: Option<U64>,
+        campaign_id: Option<String>,
+        memo: Option<String>,
+        password_hash: Option<Base64VecU8>,
+        allowed_receivers: Option<AllowedReceivers>,
     ) -> Promise {
-        assert_eq!(
-            env::signer_account_id(),
-            env::current_account_id(),
-            "Only the Smart Contract owner can call send method"
+        let mut expires_at = expires_at.map(|expires_at| expires_at.0);
+        match &campaign_id {
+            Some(campaign_id) => {
+                let campaign = self.campaigns.get(campaign_id).expect("Campaign not found");
+                let sender_id = env::predecessor_account_id();
+                assert!(
+                    sender_id == campaign.owner_id || sender_id == env::current_account_id(),
+                    "Only the campaign owner can send drops of the campaign"
+                );
+                assert_eq!(
+                    campaign.nft_contract_id,
+                    nft_contract_id.to_string(),
+                    "NFT contract doesn't match the campaign"
+                );
+                expires_at = match (expires_at, campaign.expires_at) {
+                    (Some(expires_at), Some(campaign_expires_at)) => {
+                        Some(expires_at.min(campaign_expires_at))
+                    }
+                    (expires_at, campaign_expires_at) => expires_at.or(campaign_expires_at),
+                };
+            }
+            None => self.assert_sender(),
+        }
+        assert!(
+            env::attached_deposit() > ACCESS_KEY_ALLOWANCE,
+            "Attached deposit must be greater than ACCESS_KEY_ALLOWANCE"
         );
-        let amount = env::attached_deposit();
-        Promise::new(new_account_id.to_string())
-            .create_account()
-            .add_full_access_key(new_public_key.into())
-            .transfer(amount)
-            .then(ext_self::on_account_created(
-                env::predecessor_account_id(),
-                amount.into(),
-                &env::current_account_id(),
-                NO_DEPOSIT,
-                ON_CREATE_ACCOUNT_CALLBACK_GAS,
-            ))
+        let nft_contract_id: AccountId = nft_contract_id.into();
+        assert!(
+            self.nft_contracts.contains(&nft_contract_id),
+            "NFT contract is not whitelisted"
+        );
+        let public_key: PublicKey = public_key.into();
+        assert!(!self.drop_exists(&public_key), "Account already registered");
+        assert!(
+            !self.token_in_drop(&nft_contract_id, &token_id),
+            "Token is already in a drop"
+        );
+        assert!(
+            expires_at.is_none_or(|expires_at| expires_at > env::block_timestamp()),
+            "Expiration must be in the future"
+        );
+        Self::assert_password_hash(&password_hash);
+        Self::assert_allowed_receivers(&allowed_receivers);
+        ext_nft::nft_token(
+            token_id.clone(),
+            &nft_contract_id,
+            NO_DEPOSIT,
+            NFT_TOKEN_GAS,
+        )
+        .then(ext_self::on_send_nft_token(
+            public_key,
+            SentDrop {
+                nft_contract_id,
+                token_id,
+                sender_id: env::predecessor_account_id(),
+                deposit: env::attached_deposit().into(),
+                expires_at: expires_at.map(U64),
+                campaign_id,
+                memo,
+                password_hash,
+                allowed_receivers,
+            },
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            ON_NFT_TOKEN_CALLBACK_GAS,
+        ))
     }
 
-    /// Callback after executing `create_account`.
-    pub fn on_account_created(&mut self, predecessor_account_id: AccountId, amount: U128) -> bool {
+    /// Callback after looking up the token of a drop created with `send`.
+    pub fn on_send_nft_token(&mut self, public_key: PublicKey, drop: SentDrop) -> bool {
         assert_eq!(
             env::predecessor_account_id(),
             env::current_account_id(),
             "Callback can only be called from the contract"
         );
-        let creation_succeeded = is_promise_success();
-        if !creation_succeeded {
-            // In case of failure, send attached deposit back.
-            Promise::new(predecessor_account_id).transfer(amount.into());
-        }
-        creation_succeeded
-    }
-
-    /// Callback after execution `create_account_and_claim`.
-    pub fn update_nft_storage(&mut self, public_key: PublicKey) -> bool {
         assert_eq!(
-            env::predecessor_account_id(),
-            env::current_account_id(),
-            "Callback can only be called from the contract"
+            env::promise_results_count(),
+            1,
+            "Contract expected a result on the callback"
         );
-        let creation_succeeded = is_promise_success();
-        if creation_succeeded {
-            //removing key access to pk
-            self.accounts.remove(&public_key);
-            Promise::new(env::current_account_id()).delete_key(public_key);
-        }
-        creation_succeeded
+        let token = match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                serde_json::from_slice::<Option<NftToken>>(&value).unwrap_or(None)
+            }
+            _ => None,
+        };
+        let deposit = drop.deposit.0;
+        self.on_nft_token_checked(public_key, drop.into(), deposit, token)
     }
 
-    // Method returns true is given pk is able to claim the reward
-    pub fn public_key_is_claimable(&self, public_key: Base58PublicKey) -> bool {
-        self.accounts.contains_key(&public_key.into())
+    /// Registers a drop for each `(public_key, token_id)` pair of tokens of `nft_contract_id`
+    /// and adds all the access keys in a single batch. The attached deposit is split equally
+    /// between the keys as their allowance. Fails without registering anything if any key is
+    /// already registered or repeated. The tokens are held by the contract, so only the owner
+    /// can call this method.
+    #[payable]
+    pub fn send_many(
+        &mut self,
+        nft_contract_id: ValidAccountId,
+        drops: Vec<(Base58PublicKey, TokenId)>,
+        expires_at: Option<U64>,
+    ) -> Promise {
+        Self::assert_owner();
+        assert!(!drops.is_empty(), "No drops to send");
+        let allowance = env::attached_deposit() / drops.len() as Balance;
+        assert!(
+            allowance > ACCESS_KEY_ALLOWANCE,
+            "Attached deposit must be greater than ACCESS_KEY_ALLOWANCE for each drop"
+        );
+        let nft_contract_id: AccountId = nft_contract_id.into();
+        assert!(
+            self.nft_contracts.contains(&nft_contract_id),
+            "NFT contract is not whitelisted"
+        );
+        let expires_at = expires_at.map(|expires_at| expires_at.0);
+        let mut promise = Promise::new(env::current_account_id());
+        let mut public_keys = Vec::with_capacity(drops.len());
+        for (public_key, token_id) in drops {
+            let pk: PublicKey = public_key.into();
+            self.insert_drop(
+                &pk,
+                DropInfo::new(
+                    vec![Asset::Nft {
+                        nft_contract_id: nft_contract_id.clone(),
+                        token_id,
+                        approval_id: None,
+                    }],
+                    env::predecessor_account_id(),
+                    expires_at,
+                ),
+            );
+            promise = Self::add_linkdrop_key(promise, pk.clone(), allowance);
+            public_keys.push(pk);
+        }
+        promise.then(Self::keys_added_callback(public_keys))
     }
-}
 
-// TODO: Update tests
+    /// Allows given public key to be claimed once for each of the given tokens of
+    /// `nft_contract_id` held by the contract, each claim receiving the next token.
+    /// The access key is deleted after the last claim. The attached deposit is the allowance
+    /// of the key and must cover ACCESS_KEY_ALLOWANCE for each claim. Only the owner can call
+    /// this method.
+    #[payable]
+    pub fn send_multi_use(
+        &mut self,
+        public_key: Base58PublicKey,
+        nft_contract_id: ValidAccountId,
+        token_ids: Vec<TokenId>,
+        expires_at: Option<U64>,
+    ) -> Promise {
+        Self::assert_owner();
+        assert!(!token_ids.is_empty(), "No tokens to send");
+        let uses = token_ids.len() as u64;
+        assert!(
+            env::attached_deposit() > ACCESS_KEY_ALLOWANCE * Balance::from(uses),
+            "Attached deposit must be greater than ACCESS_KEY_ALLOWANCE for each use"
+        );
+        let nft_contract_id: AccountId = nft_contract_id.into();
+        assert!(
+            self.nft_contracts.contains(&nft_contract_id),
+            "NFT contract is not whitelisted"
+        );
+        let assets = token_ids
+            .into_iter()
+            .map(|token_id| Asset::Nft {
+                nft_contract_id: nft_contract_id.clone(),
+                token_id,
+                approval_id: None,
+            })
+            .collect();
+        self.register_drop(
+            public_key.into(),
+            DropInfo {
+                remaining_uses: uses,
+                ..DropInfo::new(
+                    assets,
+                    env::predecessor_account_id(),
+                    expires_at.map(|expires_at| expires_at.0),
+                )
+            },
+            env::attached_deposit(),
+        )
+    }
 
-#[cfg(not(target_arch = "wasm32"))]
-#[cfg(test)]
-mod tests {
-    use std::convert::TryInto;
+    /// Allows given public key to be claimed `uses` times, once by default, each claim receiving
+    /// a token drawn from the pool. The attached deposit is the allowance of the key and must
+    /// cover ACCESS_KEY_ALLOWANCE for each claim. The pool is filled by the owner, so only the
+    /// owner can send its drops.
+    #[payable]
+    pub fn send_pool(
+        &mut self,
+        public_key: Base58PublicKey,
+        pool_id: String,
+        uses: Option<u64>,
+        expires_at: Option<U64>,
+    ) -> Promise {
+        Self::assert_owner();
+        assert!(self.pools.get(&pool_id).is_some(), "Pool not found");
+        let uses = uses.unwrap_or(1);
+        assert!(uses > 0, "Drop must have at least one use");
+        assert!(
+            env::attached_deposit() > ACCESS_KEY_ALLOWANCE * Balance::from(uses),
+            "Attached deposit must be greater than ACCESS_KEY_ALLOWANCE for each use"
+        );
+        self.register_drop(
+            public_key.into(),
+            DropInfo {
+                remaining_uses: uses,
+                pool_id: Some(pool_id),
+                ..DropInfo::new(
+                    vec![Asset::Pool; uses as usize],
+                    env::predecessor_account_id(),
+                    expires_at.map(|expires_at| expires_at.0),
+                )
+            },
+            env::attached_deposit(),
+        )
+    }
 
-    use near_sdk::test_utils::accounts;
-    use near_sdk::MockedBlockchain;
-    use near_sdk::{testing_env, Balance, BlockHeight, PublicKey, VMContext};
+    /// Allows given public key to be claimed `uses` times, once by default, each claim minting
+    /// a new token of `nft_contract_id` with `nft_mint`. The linkdrop must be allowed to mint on
+    /// the contract. Token ids are `{series_id}:{key hash}:{use}`, and `{token_id}` in the
+    /// metadata is replaced by the id of the token.
+    /// The attached deposit covers MINT_STORAGE_DEPOSIT for each use, and the rest is the
+    /// allowance of the key.
+    #[payable]
+    pub fn send_mint(
+        &mut self,
+        public_key: Base58PublicKey,
+        nft_contract_id: ValidAccountId,
+        series_id: String,
+        token_metadata: TokenMetadata,
+        uses: Option<u64>,
+        expires_at: Option<U64>,
+    ) -> Promise {
+        self.assert_sender();
+        let nft_contract_id: AccountId = nft_contract_id.into();
+        assert!(
+            self.nft_contracts.contains(&nft_contract_id),
+            "NFT contract is not whitelisted"
+        );
+        let uses = uses.unwrap_or(1);
+        assert!(uses > 0, "Drop must have at least one use");
+        let mint_deposit = MINT_STORAGE_DEPOSIT * Balance::from(uses);
+        let allowance = env::attached_deposit().saturating_sub(mint_deposit);
+        assert!(
+            allowance > ACCESS_KEY_ALLOWANCE * Balance::from(uses),
+            "Attached deposit must cover ACCESS_KEY_ALLOWANCE and MINT_STORAGE_DEPOSIT for each use"
+        );
+        let public_key: PublicKey = public_key.into();
+        let assets = (0..uses)
+            .map(|index| {
+                let token_id = Self::mint_token_id(&series_id, &public_key, index);
+                Asset::Mint {
+                    nft_contract_id: nft_contract_id.clone(),
+                    token_metadata: Box::new(Self::mint_metadata(&token_metadata, &token_id)),
+                    token_id,
+                    deposit: MINT_STORAGE_DEPOSIT,
+                }
+            })
+            .collect();
+        self.register_drop(
+            public_key,
+            DropInfo {
+                remaining_uses: uses,
+                ..DropInfo::new(
+                    assets,
+                    env::predecessor_account_id(),
+                    expires_at.map(|expires_at| expires_at.0),
+                )
+            },
+            allowance,
+        )
+    }
 
-    use super::*;
+    /// Allows given public key to claim several tokens held by the contract at once.
+    /// Only the owner can call this method.
+    /// The attached deposit minus ACCESS_KEY_ALLOWANCE, if any, is added to the bundle as NEAR.
+    /// Claims of bundles with a `password_hash` must pass the password, see `hash_password`.
+    #[payable]
+    pub fn send_bundle(
+        &mut self,
+        public_key: Base58PublicKey,
+        assets: Vec<AssetArgs>,
+        expires_at: Option<U64>,
+        password_hash: Option<Base64VecU8>,
+    ) -> Promise {
+        Self::assert_owner();
+        Self::assert_password_hash(&password_hash);
+        assert!(
+            env::attached_deposit() >= ACCESS_KEY_ALLOWANCE,
+            "Attached deposit must be at least ACCESS_KEY_ALLOWANCE"
+        );
+        let mut assets: Vec<Asset> = assets
+            .into_iter()
+            .map(|asset| match asset {
+                AssetArgs::Nft {
+                    nft_contract_id,
+                    token_id,
+                } => {
+                    let nft_contract_id: AccountId = nft_contract_id.into();
+                    assert!(
+                        self.nft_contracts.contains(&nft_contract_id),
+                        "NFT contract is not whitelisted"
+                    );
+                    Asset::Nft {
+                        nft_contract_id,
+                        token_id,
+                        approval_id: None,
+                    }
+                }
+                AssetArgs::Ft {
+                    ft_contract_id,
+                    amount,
+                } => {
+                    let ft_contract_id: AccountId = ft_contract_id.into();
+                    assert!(
+                        self.ft_contracts.contains(&ft_contract_id),
+                        "FT contract is not whitelisted"
+                    );
+                    Asset::Ft {
+                        ft_contract_id,
+                        amount: amount.0,
+                    }
+                }
+            })
+            .collect();
+        let amount = env::attached_deposit() - ACCESS_KEY_ALLOWANCE;
+        if amount > 0 {
+            assets.push(Asset::Near { amount });
+        }
+        assert!(!assets.is_empty(), "Bundle must have at least one asset");
+        self.register_drop(
+            public_key.into(),
+            DropInfo {
+                password_hash: password_hash.map(|hash| hash.0),
+                ..DropInfo::new(
+                    assets,
+                    env::predecessor_account_id(),
+                    expires_at.map(|expires_at| expires_at.0),
+                )
+            },
+            ACCESS_KEY_ALLOWANCE,
+        )
+    }
 
-    pub struct VMContextBuilder {
-        context: VMContext,
+    /// Allows given public key to claim the attached deposit minus ACCESS_KEY_ALLOWANCE,
+    /// which is used as the allowance of the access key.
+    #[payable]
+    pub fn send_near(&mut self, public_key: Base58PublicKey, expires_at: Option<U64>) -> Promise {
+        assert!(
+            env::attached_deposit() > ACCESS_KEY_ALLOWANCE,
+            "Attached deposit must be greater than ACCESS_KEY_ALLOWANCE"
+        );
+        self.register_drop(
+            public_key.into(),
+            DropInfo::new(
+                vec![Asset::Near {
+                    amount: env::attached_deposit() - ACCESS_KEY_ALLOWANCE,
+                }],
+                env::predecessor_account_id(),
+                expires_at.map(|expires_at| expires_at.0),
+            ),
+            ACCESS_KEY_ALLOWANCE,
+        )
     }
 
-    impl VMContextBuilder {
-        pub fn new() -> Self {
-            Self {
-                context: VMContext {
-                    current_account_id: "".to_string(),
-                    signer_account_id: "".to_string(),
-                    signer_account_pk: vec![0, 1, 2],
-                    predecessor_account_id: "".to_string(),
-                    input: vec![],
-                    block_index: 0,
-                    epoch_height: 0,
-                    block_timestamp: 0,
-                    account_balance: 0,
-                    account_locked_balance: 0,
-                    storage_usage: 10u64.pow(6),
-                    attached_deposit: 0,
-                    prepaid_gas: 10u64.pow(18),
-                    random_seed: vec![0, 1, 2],
-                    is_view: false,
-                    output_data_receivers: vec![],
-                },
+    /// Cancels a pending drop, returning its unclaimed assets to the sender and removing the
+    /// access key.
+    pub fn cancel_drop(&mut self, public_key: Base58PublicKey) -> Promise {
+        let pk = public_key.into();
+        let drop = self.find_drop(&pk).expect("Drop not found");
+        assert_eq!(
+            drop.sender_id,
+            env::predecessor_account_id(),
+            "Only the sender can cancel the drop"
+        );
+        Self::assert_returnable(&drop);
+        self.remove_drop(&pk, &drop);
+        Event::DropCancelled(vec![DropData::new(&pk, &drop.sender_id)]).emit();
+        Self::return_assets(pk, drop)
+    }
+
+    /// Returns the unclaimed assets of an expired drop to its sender and removes the access key.
+    pub fn reclaim(&mut self, public_key: Base58PublicKey) -> Promise {
+        let pk = public_key.into();
+        let drop = self.find_drop(&pk).expect("Drop not found");
+        assert_eq!(
+            drop.sender_id,
+            env::predecessor_account_id(),
+            "Only the sender can reclaim the drop"
+        );
+        assert!(drop.is_expired(), "Drop has not expired yet");
+        Self::assert_returnable(&drop);
+        self.remove_drop(&pk, &drop);
+        Event::DropExpired(vec![DropData::new(&pk, &drop.sender_id)]).emit();
+        Self::return_assets(pk, drop)
+    }
+
+    /// Retries the transfers of a failed claim to the account it created.
+    /// Can be called by the owner or the sender of the drop.
+    pub fn retry_claim(&mut self, public_key: Base58PublicKey) -> Promise {
+        let pk: PublicKey = public_key.into();
+        let mut drop = self.find_drop(&pk).expect("Drop not found");
+        Self::assert_owner_or_drop_sender(&drop);
+        let new_account_id = match drop.status {
+            DropStatus::Failed { .. } => drop
+                .new_account_id
+                .clone()
+                .expect("Only claims that created an account can be retried"),
+            _ => env::panic(b"Only failed claims can be retried"),
+        };
+        self.reserve_campaign_claim(&drop.campaign_id);
+        drop.status = DropStatus::Claiming;
+        self.draw_pool_tokens(&pk, &mut drop);
+        self.save_drop(&pk, &drop);
+        let memo = self.claim_memo(&drop);
+        Self::claim_assets(pk, drop, new_account_id, memo)
+    }
+
+    /// Replaces an undelivered NFT of a failed claim with another token of the same contract
+    /// held by the linkdrop. The transfer is sent with `retry_claim`. Failed tokens of pool drops
+    /// are already back in the pool, and `retry_claim` draws new ones.
+    pub fn replace_nft(
+        &mut self,
+        public_key: Base58PublicKey,
+        asset_index: u64,
+        token_id: TokenId,
+    ) {
+        Self::assert_owner();
+        let pk: PublicKey = public_key.into();
+        let mut drop = self.find_drop(&pk).expect("Drop not found");
+        match drop.status {
+            DropStatus::Failed { .. } => {}
+            _ => env::panic(b"Only tokens of failed claims can be replaced"),
+        }
+        let asset_index = asset_index as usize;
+        assert!(asset_index < drop.assets.len(), "Asset not found");
+        assert!(!drop.claimed[asset_index], "Asset has already been claimed");
+        match &mut drop.assets[asset_index] {
+            Asset::Nft {
+                nft_contract_id,
+                token_id: replaced,
+                approval_id,
+            } => {
+                assert!(
+                    !self.token_in_drop(nft_contract_id, &token_id),
+                    "Token is already in a drop"
+                );
+                self.drop_by_token
+                    .remove(&(nft_contract_id.clone(), replaced.clone()));
+                self.drop_by_token
+                    .insert(&(nft_contract_id.clone(), token_id.clone()), &pk);
+                *approval_id = None;
+                *replaced = token_id;
             }
+            _ => env::panic(b"Asset is not an NFT"),
         }
+        self.save_drop(&pk, &drop);
+    }
 
-        pub fn current_account_id(mut self, account_id: AccountId) -> Self {
-            self.context.current_account_id = account_id;
-            self
-        }
+    /// Claim tokens for specific account that are attached to the public key this tx is signed with.
+    /// `password` is required by password-protected drops.
+    pub fn claim(&mut self, account_id: ValidAccountId, password: Option<String>) -> Promise {
+        let account_id: AccountId = account_id.into();
+        let drop = self.start_claim(&env::signer_account_pk(), password, &account_id);
+        let memo = self.claim_memo(&drop);
+        Self::claim_assets(env::signer_account_pk(), drop, account_id, memo)
+    }
 
-        #[allow(dead_code)]
-        pub fn signer_account_id(mut self, account_id: AccountId) -> Self {
-            self.context.signer_account_id = account_id;
-            self
-        }
+    /// Create new account and and claim tokens to it.
+    /// The tokens are transferred once the account is created. If the account can't be created,
+    /// the attached deposit is refunded and the drop stays claimable.
+    /// `password` is required by password-protected drops.
+    #[payable]
+    pub fn create_account_and_claim(
+        &mut self,
+        new_account_id: ValidAccountId,
+        new_public_key: Base58PublicKey,
+        password: Option<String>,
+    ) -> Promise {
+        assert!(
+            env::attached_deposit() > CREATE_SUBACCOUNT_ALLOWANCE,
+            "Attached deposit must be greater than ACCESS_KEY_ALLOWANCE"
+        );
+        let drop = self.start_claim(
+            &env::signer_account_pk(),
+            password,
+            &new_account_id.to_string(),
+        );
+        Promise::new(new_account_id.to_string())
+            .create_account()
+            .add_full_access_key(new_public_key.into())
+            .transfer(env::attached_deposit())
+            .then(ext_self::on_account_created_and_claimed(
+                env::signer_account_pk(),
+                new_account_id.into(),
+                env::predecessor_account_id(),
+                env::attached_deposit().into(),
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                ON_CREATE_ACCOUNT_CALLBACK_GAS + Self::claim_gas(&drop),
+            ))
+    }
 
-        pub fn predecessor_account_id(mut self, account_id: AccountId) -> Self {
-            self.context.predecessor_account_id = account_id;
-            self
-        }
+    /// Create new account without linkdrop and deposit passed funds (used for creating sub accounts directly).
+    #[payable]
+    pub fn create_account(
+        &mut self,
+        new_account_id: ValidAccountId,
+        new_public_key: Base58PublicKey,
+    ) -> Promise {
+        assert_eq!(
+            env::signer_account_id(),
+            env::current_account_id(),
+            "Only the Smart Contract owner can call send method"
+        );
+        let amount = env::attached_deposit();
+        Promise::new(new_account_id.to_string())
+            .create_account()
+            .add_full_access_key(new_public_key.into())
+            .transfer(amount)
+            .then(ext_self::on_account_created(
+                new_account_id.to_string(),
+                env::predecessor_account_id(),
+                amount.into(),
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                ON_CREATE_ACCOUNT_CALLBACK_GAS,
+            ))
+    }
 
-        #[allow(dead_code)]
-        pub fn block_index(mut self, block_index: BlockHeight) -> Self {
-            self.context.block_index = block_index;
-            self
+    /// Callback after executing `create_account`.
+    pub fn on_account_created(
+        &mut self,
+        new_account_id: AccountId,
+        predecessor_account_id: AccountId,
+        amount: U128,
+    ) -> bool {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Callback can only be called from the contract"
+        );
+        let creation_succeeded = is_promise_success();
+        if creation_succeeded {
+            Event::AccountCreated(vec![AccountCreatedData {
+                account_id: new_account_id,
+            }])
+            .emit();
+        } else {
+            // In case of failure, send attached deposit back.
+            Promise::new(predecessor_account_id).transfer(amount.into());
         }
+        creation_succeeded
+    }
 
-        pub fn attached_deposit(mut self, amount: Balance) -> Self {
-            self.context.attached_deposit = amount;
-            self
-        }
+    /// Callback after creating the account of `create_account_and_claim`.
+    /// Starts the transfers to the new account, or refunds the deposit if the account wasn't created.
+    pub fn on_account_created_and_claimed(
+        &mut self,
+        public_key: PublicKey,
+        new_account_id: AccountId,
+        predecessor_account_id: AccountId,
+        amount: U128,
+    ) -> ClaimResult {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Callback can only be called from the contract"
+        );
+        let creation_succeeded = is_promise_success();
+        self.on_claim_account_created(
+            public_key,
+            new_account_id,
+            predecessor_account_id,
+            amount.into(),
+            creation_succeeded,
+        )
+    }
 
-        pub fn account_balance(mut self, amount: Balance) -> Self {
-            self.context.account_balance = amount;
-            self
-        }
+    /// Callback after transferring an asset in `claim` or `create_account_and_claim`.
+    /// `last_asset` is set for the last transfer of the claim, which releases the drop.
+    pub fn update_nft_storage(
+        &mut self,
+        public_key: PublicKey,
+        asset_index: u64,
+        last_asset: bool,
+    ) -> bool {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Callback can only be called from the contract"
+        );
+        let transfer_succeeded = is_promise_success();
+        self.on_asset_transferred(
+            public_key,
+            asset_index as usize,
+            last_asset,
+            transfer_succeeded,
+        );
+        transfer_succeeded
+    }
 
-        #[allow(dead_code)]
-        pub fn account_locked_balance(mut self, amount: Balance) -> Self {
-            self.context.account_locked_balance = amount;
-            self
+    /// Callback after adding the access keys of new drops. Marks the drops whose key was added,
+    /// so removing them deletes it, and deletes the keys of drops removed or claimed meanwhile.
+    /// If the keys couldn't be added, e.g. because the account already has one of them, the
+    /// drops stay without a key and their removal leaves the keys of the account untouched.
+    pub fn on_keys_added(&mut self, public_keys: Vec<PublicKey>) -> bool {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Callback can only be called from the contract"
+        );
+        if !is_promise_success() {
+            env::log(b"Failed to add the access keys of the drops");
+            return false;
+        }
+        for public_key in public_keys {
+            match self.accounts.get(&public_key) {
+                Some(mut drop) if drop.status != DropStatus::Claimed => {
+                    drop.key_added = true;
+                    self.accounts.insert(&public_key, &drop);
+                }
+                _ => {
+                    Promise::new(env::current_account_id()).delete_key(public_key);
+                }
+            }
         }
+        true
+    }
+
+    // Method returns true is given pk is able to claim the reward
+    pub fn public_key_is_claimable(&self, public_key: Base58PublicKey) -> bool {
+        self.find_drop(&public_key.into())
+            .is_some_and(|drop| drop.status == DropStatus::Available && !drop.is_expired())
+    }
+
+    /// Returns the NFT contracts the linkdrop accepts drops from.
+    pub fn get_nft_contracts(&self) -> Vec<AccountId> {
+        self.nft_contracts.to_vec()
+    }
+
+    /// Returns the fungible token contracts the linkdrop accepts drops from.
+    pub fn get_ft_contracts(&self) -> Vec<AccountId> {
+        self.ft_contracts.to_vec()
+    }
+
+    /// Returns the accounts besides the owner allowed to call `send` and `send_bundle`.
+    pub fn get_senders(&self) -> Vec<AccountId> {
+        self.senders.to_vec()
+    }
+
+    /// Returns the drop of the given key, including drops registered before migration.
+    pub fn get_drop(&self, public_key: Base58PublicKey) -> Option<DropView> {
+        let public_key: PublicKey = public_key.into();
+        self.find_drop(&public_key)
+            .map(|drop| DropView::new(public_key, drop))
+    }
+
+    /// Returns the campaign with its numbers of created, claimed and outstanding drops.
+    pub fn get_campaign(&self, campaign_id: String) -> Option<CampaignView> {
+        let campaign = self.campaigns.get(&campaign_id)?;
+        Some(CampaignView::new(campaign_id, campaign))
+    }
+
+    /// Returns a page of the campaigns.
+    pub fn get_campaigns(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<CampaignView> {
+        let keys = self.campaigns.keys_as_vector();
+        let values = self.campaigns.values_as_vector();
+        Self::page(keys.len(), from_index, limit)
+            .map(|index| CampaignView::new(keys.get(index).unwrap(), values.get(index).unwrap()))
+            .collect()
+    }
+
+    /// Returns the pool with its number of tokens not drawn yet.
+    pub fn get_pool(&self, pool_id: String) -> Option<PoolView> {
+        let pool = self.pools.get(&pool_id)?;
+        Some(PoolView::new(pool_id, pool))
+    }
+
+    /// Returns the drop holding the given token until it is claimed.
+    pub fn get_drop_by_token(
+        &self,
+        nft_contract_id: ValidAccountId,
+        token_id: TokenId,
+    ) -> Option<DropView> {
+        let public_key = self
+            .drop_by_token
+            .get(&(nft_contract_id.into(), token_id))?;
+        self.find_drop(&public_key)
+            .map(|drop| DropView::new(public_key, drop))
+    }
+
+    /// Returns a page of the drops, claimed ones included.
+    /// Drops registered before migration are only listed once they are claimed or updated.
+    pub fn get_drops(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<DropView> {
+        let keys = self.accounts.keys_as_vector();
+        let values = self.accounts.values_as_vector();
+        Self::page(keys.len(), from_index, limit)
+            .map(|index| DropView::new(keys.get(index).unwrap(), values.get(index).unwrap()))
+            .collect()
+    }
+
+    /// Returns a page of the drops created by the given sender.
+    pub fn get_drops_for_sender(
+        &self,
+        sender_id: ValidAccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<DropView> {
+        let set = match self.drops_per_sender.get(&sender_id.into()) {
+            Some(set) => set,
+            None => return vec![],
+        };
+        let keys = set.as_vector();
+        Self::page(keys.len(), from_index, limit)
+            .map(|index| {
+                let public_key = keys.get(index).unwrap();
+                let drop = self.accounts.get(&public_key).unwrap();
+                DropView::new(public_key, drop)
+            })
+            .collect()
+    }
+
+    /// Returns the number of drops listed by `get_drops`.
+    pub fn get_drop_count(&self) -> U64 {
+        U64(self.accounts.len())
+    }
+}
+
+impl LinkDrop {
+    fn assert_owner() {
+        assert_eq!(
+            env::signer_account_id(),
+            env::current_account_id(),
+            "Only the Smart Contract owner can call this method"
+        );
+    }
+
+    fn assert_owner_or_drop_sender(drop: &DropInfo) {
+        assert!(
+            env::signer_account_id() == env::current_account_id()
+                || env::predecessor_account_id() == drop.sender_id,
+            "Only the Smart Contract owner or the sender can call this method"
+        );
+    }
+
+    /// Drops of `send` and `send_mint` can be created by the owner and the allowed senders.
+    fn assert_sender(&self) {
+        let sender_id = env::predecessor_account_id();
+        assert!(
+            sender_id == env::current_account_id() || self.senders.contains(&sender_id),
+            "Only the Smart Contract owner or allowed senders can create drops"
+        );
+    }
+
+    /// Returns the drop of the given key, falling back to the drops registered before migration.
+    fn find_drop(&self, public_key: &PublicKey) -> Option<DropInfo> {
+        self.accounts.get(public_key).or_else(|| {
+            self.legacy_accounts
+                .get(public_key)
+                .map(|token_id| DropInfo {
+                    key_added: true,
+                    ..DropInfo::new(
+                        vec![Asset::Nft {
+                            nft_contract_id: self.legacy_nft_contract_id.clone(),
+                            token_id,
+                            approval_id: None,
+                        }],
+                        env::current_account_id(),
+                        None,
+                    )
+                })
+        })
+    }
+
+    /// Returns the drop of the given key and moves it to `Claiming` until the claim finishes,
+    /// so the key can't claim again in the meantime. Panics if the drop doesn't exist, has expired,
+    /// isn't available, `password` doesn't match its password hash or `receiver_id` isn't allowed.
+    /// Tokens of the claim are drawn from the pool of the drop, if any.
+    fn start_claim(
+        &mut self,
+        public_key: &PublicKey,
+        password: Option<String>,
+        receiver_id: &AccountId,
+    ) -> DropInfo {
+        let drop = self.find_drop(public_key);
+        // Check if pk is in accounts lookupmap
+        assert_ne!(drop, None, "Signer must be eligible to claim the NFT");
+        let mut drop = drop.unwrap();
+        assert!(!drop.is_expired(), "Drop has expired");
+        self.reserve_campaign_claim(&drop.campaign_id);
+        match drop.status {
+            DropStatus::Available => {}
+            DropStatus::Claiming => env::panic(b"Drop is being claimed"),
+            DropStatus::Claimed => env::panic(b"Drop has already been claimed"),
+            DropStatus::Failed { .. } => env::panic(b"Drop claim failed"),
+        }
+        if let Some(password_hash) = &drop.password_hash {
+            let password = password.unwrap_or_else(|| env::panic(b"Password is required"));
+            assert!(
+                Self::hash_password(public_key, receiver_id, &password) == *password_hash,
+                "Wrong password"
+            );
+        }
+        if let Some(allowed_receivers) = &drop.allowed_receivers {
+            assert!(
+                allowed_receivers.allows(receiver_id),
+                "Account {} is not allowed to receive the drop",
+                receiver_id
+            );
+        }
+        drop.status = DropStatus::Claiming;
+        self.draw_pool_tokens(public_key, &mut drop);
+        self.save_drop(public_key, &drop);
+        drop
+    }
+
+    /// Hash of a drop password, salted with the key of the drop and the account receiving the
+    /// claim: `sha256(public_key ++ receiver_id ++ password)`, where `public_key` is the 33 bytes
+    /// of the serialized key, its curve type (0 for ed25519) followed by the 32 key bytes.
+    /// A password seen in a claim can't be reused to claim to another account.
+    fn hash_password(public_key: &PublicKey, receiver_id: &AccountId, password: &str) -> Vec<u8> {
+        env::sha256(
+            &[
+                public_key.as_slice(),
+                receiver_id.as_bytes(),
+                password.as_bytes(),
+            ]
+            .concat(),
+        )
+    }
+
+    /// Panics unless the password hash of a new drop is a sha256 hash.
+    fn assert_password_hash(password_hash: &Option<Base64VecU8>) {
+        assert!(
+            password_hash.as_ref().is_none_or(|hash| hash.0.len() == 32),
+            "Password hash must be a sha256 hash"
+        );
+    }
+
+    /// Panics if no account could receive a new drop restricted to `allowed_receivers`.
+    fn assert_allowed_receivers(allowed_receivers: &Option<AllowedReceivers>) {
+        match allowed_receivers {
+            Some(AllowedReceivers::Accounts(account_ids)) => {
+                assert!(
+                    !account_ids.is_empty(),
+                    "Allowed receivers must not be empty"
+                )
+            }
+            Some(AllowedReceivers::Suffix(suffix)) => assert!(
+                !suffix.trim_start_matches('.').is_empty(),
+                "Allowed receivers suffix must not be empty"
+            ),
+            None => {}
+        }
+    }
+
+    /// Panics unless the assets of the drop can be returned to its sender.
+    fn assert_returnable(drop: &DropInfo) {
+        match drop.status {
+            DropStatus::Available | DropStatus::Failed { .. } => {}
+            DropStatus::Claiming => env::panic(b"Drop is being claimed"),
+            DropStatus::Claimed => env::panic(b"Drop has already been claimed"),
+        }
+    }
+
+    /// Stores an updated drop, moving drops from the legacy map to `accounts`.
+    fn save_drop(&mut self, public_key: &PublicKey, drop: &DropInfo) {
+        if self.legacy_accounts.remove(public_key).is_some() {
+            self.add_sender_drop(&drop.sender_id, public_key);
+            for token in drop.unclaimed_tokens() {
+                self.drop_by_token.insert(&token, public_key);
+            }
+        }
+        self.accounts.insert(public_key, drop);
+    }
+
+    fn drop_exists(&self, public_key: &PublicKey) -> bool {
+        self.accounts.get(public_key).is_some() || self.legacy_accounts.contains_key(public_key)
+    }
+
+    fn remove_drop(&mut self, public_key: &PublicKey, drop: &DropInfo) {
+        self.accounts.remove(public_key);
+        self.legacy_accounts.remove(public_key);
+        for token in drop.unclaimed_tokens() {
+            self.drop_by_token.remove(&token);
+        }
+        // Tokens drawn by a failed claim go back to the pool
+        if let Some(pool_id) = &drop.pool_id {
+            for (_, token_id) in drop.unclaimed_tokens() {
+                self.return_to_pool(pool_id, token_id);
+            }
+        }
+        self.update_campaign(&drop.campaign_id, |campaign| campaign.removed += 1);
+        if let Some(mut keys) = self.drops_per_sender.get(&drop.sender_id) {
+            keys.remove(public_key);
+            if keys.is_empty() {
+                self.drops_per_sender.remove(&drop.sender_id);
+            } else {
+                self.drops_per_sender.insert(&drop.sender_id, &keys);
+            }
+        }
+    }
+
+    fn update_campaign(
+        &mut self,
+        campaign_id: &Option<String>,
+        update: impl FnOnce(&mut Campaign),
+    ) {
+        if let Some(campaign_id) = campaign_id {
+            if let Some(mut campaign) = self.campaigns.get(campaign_id) {
+                update(&mut campaign);
+                self.campaigns.insert(campaign_id, &campaign);
+            }
+        }
+    }
+
+    /// Counts a claim starting against the `max_claims` of the campaign until it finishes, so
+    /// concurrent claims can't exceed it. Panics if the campaign has no claims left.
+    fn reserve_campaign_claim(&mut self, campaign_id: &Option<String>) {
+        self.update_campaign(campaign_id, |campaign| {
+            assert!(
+                campaign
+                    .max_claims
+                    .is_none_or(|max_claims| campaign.claimed + campaign.claiming < max_claims),
+                "Campaign has no claims left"
+            );
+            campaign.claiming += 1;
+        });
+    }
+
+    /// Replaces the `Asset::Pool` assets of the next claim of the drop with tokens drawn from
+    /// its pool. Panics if the pool runs out of tokens.
+    fn draw_pool_tokens(&mut self, public_key: &PublicKey, drop: &mut DropInfo) {
+        let pool_id = match &drop.pool_id {
+            Some(pool_id) => pool_id.clone(),
+            None => return,
+        };
+        let indices: Vec<usize> = drop
+            .next_claim_assets()
+            .filter(|(_, asset)| matches!(asset, Asset::Pool))
+            .map(|(index, _)| index)
+            .collect();
+        if indices.is_empty() {
+            return;
+        }
+        let mut pool = self.pools.get(&pool_id).expect("Pool not found");
+        for index in indices {
+            let token_id = pool.draw().unwrap_or_else(|| env::panic(b"Pool is empty"));
+            let token = (pool.nft_contract_id.clone(), token_id.clone());
+            self.pooled_tokens.remove(&token);
+            self.drop_by_token.insert(&token, public_key);
+            drop.assets[index] = Asset::Nft {
+                nft_contract_id: pool.nft_contract_id.clone(),
+                token_id,
+                approval_id: None,
+            };
+        }
+        self.pools.insert(&pool_id, &pool);
+    }
+
+    fn return_to_pool(&mut self, pool_id: &str, token_id: TokenId) {
+        let pool_id = pool_id.to_string();
+        if let Some(mut pool) = self.pools.get(&pool_id) {
+            self.pooled_tokens
+                .insert(&(pool.nft_contract_id.clone(), token_id.clone()));
+            pool.tokens.push(&token_id);
+            self.pools.insert(&pool_id, &pool);
+        }
+    }
+
+    /// Takes ACCESS_KEY_ALLOWANCE from the allowance deposit of `account_id`.
+    /// Returns `false` and logs the reason if the deposit doesn't cover it.
+    fn take_allowance_deposit(&mut self, account_id: &AccountId) -> bool {
+        let balance = self.allowance_deposits.get(account_id).unwrap_or(0);
+        if balance < ACCESS_KEY_ALLOWANCE {
+            env::log(b"Not enough allowance deposit");
+            return false;
+        }
+        self.allowance_deposits
+            .insert(account_id, &(balance - ACCESS_KEY_ALLOWANCE));
+        true
+    }
+
+    /// Whether the token is held for a drop, including the tokens waiting in a pool.
+    fn token_in_drop(&self, nft_contract_id: &AccountId, token_id: &TokenId) -> bool {
+        let token = (nft_contract_id.clone(), token_id.clone());
+        self.drop_by_token.contains_key(&token) || self.pooled_tokens.contains(&token)
+    }
+
+    fn add_sender_drop(&mut self, sender_id: &AccountId, public_key: &PublicKey) {
+        let mut keys = self.drops_per_sender.get(sender_id).unwrap_or_else(|| {
+            let mut prefix = vec![6];
+            prefix.extend(env::sha256(sender_id.as_bytes()));
+            UnorderedSet::new(prefix)
+        });
+        keys.insert(public_key);
+        self.drops_per_sender.insert(sender_id, &keys);
+    }
+
+    /// Indices of a page of a list of `len` items, starting at `from_index`.
+    fn page(len: u64, from_index: Option<U128>, limit: Option<u64>) -> std::ops::Range<u64> {
+        let start = from_index
+            .map_or(0, |from_index| from_index.0 as u64)
+            .min(len);
+        let end = limit.map_or(len, |limit| start.saturating_add(limit).min(len));
+        start..end
+    }
+
+    /// Registers a drop of `send` if the linkdrop owns or is approved for its token, keeping
+    /// the approval to transfer tokens the linkdrop doesn't own.
+    /// Tokens held by the linkdrop can only be sent by the owner or in a campaign, and approved
+    /// tokens only by their owner.
+    /// Otherwise logs the reason and refunds the deposit, which is the allowance of the key.
+    fn on_nft_token_checked(
+        &mut self,
+        public_key: PublicKey,
+        mut drop: DropInfo,
+        deposit: Balance,
+        token: Option<NftToken>,
+    ) -> bool {
+        let linkdrop_id = env::current_account_id();
+        let approval_id = match &token {
+            Some(token) if token.owner_id != linkdrop_id => token
+                .approved_account_ids
+                .as_ref()
+                .and_then(|approved| approved.get(&linkdrop_id).copied()),
+            _ => None,
+        };
+        let token_taken = drop
+            .unclaimed_tokens()
+            .any(|(nft_contract_id, token_id)| self.token_in_drop(&nft_contract_id, &token_id));
+        let error = match &token {
+            None => Some("Token not found"),
+            Some(token) if token.owner_id != linkdrop_id && approval_id.is_none() => {
+                Some("Linkdrop is neither the owner nor approved for the token")
+            }
+            Some(token)
+                if token.owner_id == linkdrop_id
+                    && drop.sender_id != linkdrop_id
+                    && drop.campaign_id.is_none() =>
+            {
+                Some("Only the owner can send tokens held by the linkdrop")
+            }
+            Some(token) if token.owner_id != linkdrop_id && token.owner_id != drop.sender_id => {
+                Some("Only the owner of an approved token can send it")
+            }
+            _ if self.drop_exists(&public_key) => Some("Account already registered"),
+            _ if token_taken => Some("Token is already in a drop"),
+            _ if drop.is_expired() => Some("Expiration must be in the future"),
+            _ => None,
+        };
+        if let Some(error) = error {
+            env::log(error.as_bytes());
+            Promise::new(drop.sender_id).transfer(deposit);
+            return false;
+        }
+        if let Asset::Nft {
+            approval_id: drop_approval_id,
+            ..
+        } = &mut drop.assets[0]
+        {
+            *drop_approval_id = approval_id;
+        }
+        self.register_drop(public_key, drop, deposit);
+        true
+    }
+
+    /// Stores a new drop and adds its access key, see `on_keys_added`.
+    fn register_drop(
+        &mut self,
+        public_key: PublicKey,
+        drop: DropInfo,
+        allowance: Balance,
+    ) -> Promise {
+        self.insert_drop(&public_key, drop);
+        Self::add_linkdrop_key(
+            Promise::new(env::current_account_id()),
+            public_key.clone(),
+            allowance,
+        )
+        .then(Self::keys_added_callback(vec![public_key]))
+    }
+
+    /// Stores a new drop without adding its access key.
+    fn insert_drop(&mut self, public_key: &PublicKey, drop: DropInfo) {
+        assert!(!drop.is_expired(), "Expiration must be in the future");
+        assert!(!self.drop_exists(public_key), "Account already registered");
+        for (nft_contract_id, token_id) in drop.unclaimed_tokens() {
+            assert!(
+                !self.token_in_drop(&nft_contract_id, &token_id),
+                "Token is already in a drop"
+            );
+            self.drop_by_token
+                .insert(&(nft_contract_id, token_id), public_key);
+        }
+        self.accounts.insert(public_key, &drop);
+        self.add_sender_drop(&drop.sender_id, public_key);
+        self.update_campaign(&drop.campaign_id, |campaign| campaign.created += 1);
+        Event::DropCreated(vec![DropData::new(public_key, &drop.sender_id)]).emit();
+    }
+
+    /// Parses the message of a drop registered with `nft_transfer_call` or `ft_transfer_call`.
+    /// Returns `None` and logs the reason if the message is invalid, the key is already registered
+    /// or the drop would already be expired.
+    fn parse_drop_msg(&self, msg: &str) -> Option<DropMsg> {
+        let drop_msg = match serde_json::from_str::<DropMsg>(msg) {
+            Ok(drop_msg) => drop_msg,
+            Err(_) => {
+                env::log(b"Invalid drop message");
+                return None;
+            }
+        };
+        if self.drop_exists(&drop_msg.public_key.clone().into()) {
+            env::log(b"Account already registered");
+            return None;
+        }
+        if let Some(expires_at) = &drop_msg.expires_at {
+            if expires_at.0 <= env::block_timestamp() {
+                env::log(b"Expiration must be in the future");
+                return None;
+            }
+        }
+        if let Some(password_hash) = &drop_msg.password_hash {
+            if password_hash.0.len() != 32 {
+                env::log(b"Password hash must be a sha256 hash");
+                return None;
+            }
+        }
+        Some(drop_msg)
+    }
+
+    /// Memo of the NFT transfers of a claim, from the drop or else from its campaign.
+    /// `{campaign}` and `{claimed_at}` in the memo are replaced by the campaign id and
+    /// the block timestamp of the claim in nanoseconds.
+    fn claim_memo(&self, drop: &DropInfo) -> Option<String> {
+        let memo = drop.memo.clone().or_else(|| {
+            let campaign_id = drop.campaign_id.as_ref()?;
+            self.campaigns.get(campaign_id)?.memo
+        })?;
+        Some(
+            memo.replace("{campaign}", drop.campaign_id.as_deref().unwrap_or(""))
+                .replace("{claimed_at}", &env::block_timestamp().to_string()),
+        )
+    }
+
+    /// Transfers the assets of the next claim of a drop to `receiver_id` one after another.
+    /// Each transfer is followed by `update_nft_storage`, so a failed asset stays claimable
+    /// while the rest of the bundle is delivered. NFTs are transferred with `memo`.
+    fn claim_assets(
+        public_key: PublicKey,
+        drop: DropInfo,
+        receiver_id: AccountId,
+        memo: Option<String>,
+    ) -> Promise {
+        let last_index = drop
+            .next_claim_assets()
+            .last()
+            .map(|(index, _)| index)
+            .expect("Drop has no assets to claim");
+        let mut promise = None;
+        for (index, (asset, claimed)) in drop.assets.into_iter().zip(drop.claimed).enumerate() {
+            if claimed || index > last_index {
+                continue;
+            }
+            promise = Some(
+                Self::transfer_asset(asset, receiver_id.clone(), promise, memo.clone()).then(
+                    ext_self::update_nft_storage(
+                        public_key.clone(),
+                        index as u64,
+                        index == last_index,
+                        &env::current_account_id(),
+                        NO_DEPOSIT,
+                        TRANSFER_FROM_GAS,
+                    ),
+                ),
+            );
+        }
+        promise.unwrap()
+    }
+
+    /// Transfers the assets of the drop to the account created by the claim. If the account wasn't
+    /// created, the deposit is returned to the payer and the drop becomes available again.
+    fn on_claim_account_created(
+        &mut self,
+        public_key: PublicKey,
+        new_account_id: AccountId,
+        predecessor_account_id: AccountId,
+        amount: Balance,
+        succeeded: bool,
+    ) -> ClaimResult {
+        let mut drop = self.find_drop(&public_key).expect("Drop not found");
+        if !succeeded {
+            if amount > 0 {
+                Promise::new(predecessor_account_id).transfer(amount);
+            }
+            drop.status = DropStatus::Available;
+            self.update_campaign(&drop.campaign_id, |campaign| campaign.claiming -= 1);
+            self.save_drop(&public_key, &drop);
+            Event::ClaimFailed(vec![ClaimFailedData {
+                public_key: Base58PublicKey(public_key),
+                reason: format!("Failed to create account {}", new_account_id),
+            }])
+            .emit();
+            return ClaimResult {
+                failed_step: Some(ClaimStep::CreateAccount),
+                refund: U128(amount),
+            };
+        }
+        Event::AccountCreated(vec![AccountCreatedData {
+            account_id: new_account_id.clone(),
+        }])
+        .emit();
+        drop.new_account_id = Some(new_account_id.clone());
+        self.save_drop(&public_key, &drop);
+        let memo = self.claim_memo(&drop);
+        Self::claim_assets(public_key, drop, new_account_id, memo);
+        ClaimResult {
+            failed_step: None,
+            refund: U128(0),
+        }
+    }
+
+    /// Gas needed by `claim_assets` to transfer the assets of the next claim of the drop.
+    fn claim_gas(drop: &DropInfo) -> Gas {
+        drop.next_claim_assets()
+            .map(|(_, asset)| match asset {
+                Asset::Ft { .. } => STORAGE_DEPOSIT_GAS + 2 * TRANSFER_FROM_GAS,
+                Asset::Mint { .. } => NFT_MINT_GAS + TRANSFER_FROM_GAS,
+                _ => 2 * TRANSFER_FROM_GAS,
+            })
+            .sum()
+    }
+
+    /// Marks the asset as received by the claimer and finalizes the claim after its last transfer.
+    /// A successful claim uses the drop once. Once it has no uses left, the drop is marked as
+    /// claimed and the access key is deleted. Otherwise the drop becomes available again, or
+    /// failed with the reason if the claim created an account that didn't receive every asset
+    /// or an approved NFT couldn't be transferred.
+    fn on_asset_transferred(
+        &mut self,
+        public_key: PublicKey,
+        asset_index: usize,
+        last_asset: bool,
+        succeeded: bool,
+    ) {
+        let mut drop = match self.find_drop(&public_key) {
+            Some(drop) => drop,
+            None => return,
+        };
+        if succeeded {
+            drop.claimed[asset_index] = true;
+            if let Asset::Nft {
+                nft_contract_id,
+                token_id,
+                ..
+            } = &drop.assets[asset_index]
+            {
+                self.drop_by_token
+                    .remove(&(nft_contract_id.clone(), token_id.clone()));
+            }
+        } else {
+            let asset = &drop.assets[asset_index];
+            let reason = match (&drop.new_account_id, asset) {
+                (Some(new_account_id), _) => Some(format!(
+                    "Failed to transfer {} to {}",
+                    asset, new_account_id
+                )),
+                (
+                    None,
+                    Asset::Nft {
+                        approval_id: Some(_),
+                        ..
+                    },
+                ) => Some(format!(
+                    "Failed to transfer {}, the approval may have been revoked",
+                    asset
+                )),
+                _ => None,
+            };
+            if let Some(reason) = reason {
+                drop.status = DropStatus::Failed { reason };
+            }
+            if let Some(pool_id) = &drop.pool_id {
+                // The token drawn from the pool goes back to it
+                let asset = std::mem::replace(&mut drop.assets[asset_index], Asset::Pool);
+                if let Asset::Nft {
+                    nft_contract_id,
+                    token_id,
+                    ..
+                } = asset
+                {
+                    self.drop_by_token
+                        .remove(&(nft_contract_id, token_id.clone()));
+                    self.return_to_pool(pool_id, token_id);
+                }
+            }
+        }
+        if last_asset {
+            // A claim of a drop with several uses left transfers a single asset
+            let claim_succeeded = if drop.remaining_uses > 1 {
+                succeeded
+            } else {
+                drop.unclaimed_assets().next().is_none()
+            };
+            let claimed = claim_succeeded && drop.remaining_uses <= 1;
+            self.update_campaign(&drop.campaign_id, |campaign| {
+                campaign.claiming -= 1;
+                if claimed {
+                    campaign.claimed += 1;
+                }
+            });
+            if claim_succeeded && drop.remaining_uses > 1 {
+                drop.remaining_uses -= 1;
+                drop.status = DropStatus::Available;
+                drop.new_account_id = None;
+            } else if claim_succeeded {
+                drop.remaining_uses = 0;
+                drop.status = DropStatus::Claimed;
+                //removing key access to pk
+                if drop.key_added {
+                    Promise::new(env::current_account_id()).delete_key(public_key.clone());
+                }
+            }
+            if claim_succeeded {
+                Event::DropClaimed(vec![DropClaimedData {
+                    public_key: Base58PublicKey(public_key.clone()),
+                    sender_id: drop.sender_id.clone(),
+                    remaining_uses: U64(drop.remaining_uses),
+                }])
+                .emit();
+            } else {
+                let reason = match &drop.status {
+                    DropStatus::Failed { reason } => reason.clone(),
+                    _ => {
+                        drop.status = DropStatus::Available;
+                        "Some assets couldn't be transferred".to_string()
+                    }
+                };
+                Event::ClaimFailed(vec![ClaimFailedData {
+                    public_key: Base58PublicKey(public_key.clone()),
+                    reason,
+                }])
+                .emit();
+            }
+        }
+        self.save_drop(&public_key, &drop);
+    }
+
+    /// Deletes the access key of a removed drop, if the contract added it, and returns its
+    /// unclaimed assets to the sender.
+    /// The allowance of the key isn't refunded: transactions signed with the key, including
+    /// failed ones, burn gas from it and the contract can't tell how much is left.
+    /// Assets of drops sent by the contract itself stay in the contract, pool tokens go back to
+    /// the pool and approved NFTs never left their owner. Only the owner can revoke the approval
+    /// of the linkdrop with `nft_revoke`. The storage deposit of tokens that weren't minted is
+    /// refunded.
+    fn return_assets(public_key: PublicKey, drop: DropInfo) -> Promise {
+        let mut promise = Promise::new(env::current_account_id());
+        if drop.key_added {
+            promise = promise.delete_key(public_key);
+        }
+        if drop.sender_id == env::current_account_id() {
+            return promise;
+        }
+        for (asset, claimed) in drop.assets.into_iter().zip(drop.claimed) {
+            if claimed {
+                continue;
+            }
+            promise = match asset {
+                Asset::Nft {
+                    approval_id: Some(_),
+                    ..
+                } => promise,
+                _ if drop.pool_id.is_some() => promise,
+                Asset::Mint { deposit, .. } => Self::transfer_asset(
+                    Asset::Near { amount: deposit },
+                    drop.sender_id.clone(),
+                    Some(promise),
+                    None,
+                ),
+                asset => Self::transfer_asset(asset, drop.sender_id.clone(), Some(promise), None),
+            };
+        }
+        promise
+    }
+
+    /// Transfers the asset of a drop to `receiver_id`, after `after` if given.
+    /// The returned promise is the last one of the transfer, so its result tells if the asset was received.
+    /// NFTs are transferred with `memo`.
+    fn transfer_asset(
+        asset: Asset,
+        receiver_id: AccountId,
+        after: Option<Promise>,
+        memo: Option<String>,
+    ) -> Promise {
+        let then = |promise: Promise| match after {
+            Some(after) => after.then(promise),
+            None => promise,
+        };
+        match asset {
+            Asset::Nft {
+                nft_contract_id,
+                token_id,
+                approval_id,
+            } => then(ext_nft::nft_transfer(
+                receiver_id,
+                token_id,
+                approval_id,
+                memo,
+                &nft_contract_id,
+                1,
+                TRANSFER_FROM_GAS,
+            )),
+            Asset::Ft {
+                ft_contract_id,
+                amount,
+            } => then(ext_ft::storage_deposit(
+                Some(receiver_id.clone()),
+                Some(true),
+                &ft_contract_id,
+                FT_STORAGE_DEPOSIT,
+                STORAGE_DEPOSIT_GAS,
+            ))
+            .then(ext_ft::ft_transfer(
+                receiver_id,
+                amount.into(),
+                None,
+                &ft_contract_id,
+                1,
+                TRANSFER_FROM_GAS,
+            )),
+            Asset::Near { amount } => then(Promise::new(receiver_id).transfer(amount)),
+            Asset::Pool => env::panic(b"Token of the pool was not drawn"),
+            Asset::Mint {
+                nft_contract_id,
+                token_id,
+                token_metadata,
+                deposit,
+            } => then(ext_nft::nft_mint(
+                token_id,
+                receiver_id,
+                *token_metadata,
+                &nft_contract_id,
+                deposit,
+                NFT_MINT_GAS,
+            )),
+        }
+    }
+
+    /// Id of the token minted by the use `index` of a lazy-mint drop.
+    fn mint_token_id(series_id: &str, public_key: &PublicKey, index: u64) -> TokenId {
+        let key_hash: String = env::sha256(public_key)
+            .iter()
+            .take(8)
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        format!("{}:{}:{}", series_id, key_hash, index)
+    }
+
+    /// Metadata of a minted token, with `{token_id}` replaced in the string fields of the
+    /// metadata template. Replacing in the fields keeps the token id from changing the JSON.
+    fn mint_metadata(template: &TokenMetadata, token_id: &str) -> TokenMetadata {
+        let mut metadata = serde_json::to_value(template).unwrap();
+        if let serde_json::Value::Object(fields) = &mut metadata {
+            for field in fields.values_mut() {
+                if let serde_json::Value::String(value) = field {
+                    *value = value.replace("{token_id}", token_id);
+                }
+            }
+        }
+        serde_json::from_value(metadata).expect("Invalid token metadata")
+    }
+
+    /// Callback that marks the drops of `public_keys` once their access keys are added.
+    fn keys_added_callback(public_keys: Vec<PublicKey>) -> Promise {
+        ext_self::on_keys_added(
+            public_keys,
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            ON_KEYS_ADDED_CALLBACK_GAS,
+        )
+    }
+
+    /// Adds a function call access key to the contract that can only claim the linkdrop.
+    fn add_linkdrop_key(promise: Promise, public_key: PublicKey, allowance: u128) -> Promise {
+        promise.add_access_key(
+            public_key,
+            allowance,
+            env::current_account_id(),
+            // add_access_key allows given pk to call functions claim or create_account_and_claim
+            LINKDROP_METHODS.to_vec(),
+        )
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenReceiver for LinkDrop {
+    /// Registers a drop for the token transferred with `nft_transfer_call`.
+    /// `msg` must be a JSON object with the `public_key` of the link, e.g.
+    /// `{"public_key": "ed25519:..."}`. The access key allowance is taken from the allowance
+    /// deposit of the previous owner, see `deposit_allowance`.
+    /// Returns `true` to send the token back to its previous owner when the drop is rejected.
+    fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: TokenId,
+        msg: String,
+    ) -> PromiseOrValue<bool> {
+        let nft_contract_id = env::predecessor_account_id();
+        if !self.nft_contracts.contains(&nft_contract_id) {
+            env::log(b"NFT contract is not whitelisted");
+            return PromiseOrValue::Value(true);
+        }
+        if self.token_in_drop(&nft_contract_id, &token_id) {
+            env::log(b"Token is already in a drop");
+            return PromiseOrValue::Value(true);
+        }
+        let drop_msg = match self.parse_drop_msg(&msg) {
+            Some(drop_msg) => drop_msg,
+            None => return PromiseOrValue::Value(true),
+        };
+        if !self.take_allowance_deposit(&previous_owner_id) {
+            return PromiseOrValue::Value(true);
+        }
+        env::log(
+            format!(
+                "Token {} of {} registered as a drop by {}",
+                token_id, previous_owner_id, sender_id
+            )
+            .as_bytes(),
+        );
+        self.register_drop(
+            drop_msg.public_key.into(),
+            DropInfo {
+                memo: drop_msg.memo,
+                password_hash: drop_msg.password_hash.map(|hash| hash.0),
+                ..DropInfo::new(
+                    vec![Asset::Nft {
+                        nft_contract_id,
+                        token_id,
+                        approval_id: None,
+                    }],
+                    previous_owner_id,
+                    drop_msg.expires_at.map(|expires_at| expires_at.0),
+                )
+            },
+            ACCESS_KEY_ALLOWANCE,
+        );
+        PromiseOrValue::Value(false)
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenApprovalReceiver for LinkDrop {
+    /// Registers a drop for the token the linkdrop was approved for with `nft_approve`.
+    /// `msg` has the same format as in `nft_on_transfer`. The token stays with its owner, and
+    /// the claim transfers it using `approval_id`. The access key allowance is taken from the
+    /// allowance deposit of the owner, see `deposit_allowance`.
+    fn nft_on_approve(
+        &mut self,
+        token_id: TokenId,
+        owner_id: AccountId,
+        approval_id: u64,
+        msg: String,
+    ) -> PromiseOrValue<String> {
+        let nft_contract_id = env::predecessor_account_id();
+        if !self.nft_contracts.contains(&nft_contract_id) {
+            env::log(b"NFT contract is not whitelisted");
+            return PromiseOrValue::Value("rejected".to_string());
+        }
+        if self.token_in_drop(&nft_contract_id, &token_id) {
+            env::log(b"Token is already in a drop");
+            return PromiseOrValue::Value("rejected".to_string());
+        }
+        let drop_msg = match self.parse_drop_msg(&msg) {
+            Some(drop_msg) => drop_msg,
+            None => return PromiseOrValue::Value("rejected".to_string()),
+        };
+        if !self.take_allowance_deposit(&owner_id) {
+            return PromiseOrValue::Value("rejected".to_string());
+        }
+        env::log(
+            format!(
+                "Approved token {} of {} registered as a drop",
+                token_id, owner_id
+            )
+            .as_bytes(),
+        );
+        self.register_drop(
+            drop_msg.public_key.into(),
+            DropInfo {
+                memo: drop_msg.memo,
+                password_hash: drop_msg.password_hash.map(|hash| hash.0),
+                ..DropInfo::new(
+                    vec![Asset::Nft {
+                        nft_contract_id,
+                        token_id,
+                        approval_id: Some(approval_id),
+                    }],
+                    owner_id,
+                    drop_msg.expires_at.map(|expires_at| expires_at.0),
+                )
+            },
+            ACCESS_KEY_ALLOWANCE,
+        );
+        PromiseOrValue::Value("registered".to_string())
+    }
+}
+
+#[near_bindgen]
+impl FungibleTokenReceiver for LinkDrop {
+    /// Registers a drop for the tokens transferred with `ft_transfer_call`.
+    /// `msg` has the same format as in `nft_on_transfer`. The claim registers the receiver on the
+    /// token contract before transferring the tokens. The access key allowance is taken from the
+    /// allowance deposit of the sender, see `deposit_allowance`.
+    /// Returns the whole `amount` to refund it to the sender when the drop is rejected.
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: ValidAccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        let ft_contract_id = env::predecessor_account_id();
+        if !self.ft_contracts.contains(&ft_contract_id) {
+            env::log(b"FT contract is not whitelisted");
+            return PromiseOrValue::Value(amount);
+        }
+        if amount.0 == 0 {
+            env::log(b"Amount must be positive");
+            return PromiseOrValue::Value(amount);
+        }
+        let drop_msg = match self.parse_drop_msg(&msg) {
+            Some(drop_msg) => drop_msg,
+            None => return PromiseOrValue::Value(amount),
+        };
+        let sender_id: AccountId = sender_id.into();
+        if !self.take_allowance_deposit(&sender_id) {
+            return PromiseOrValue::Value(amount);
+        }
+        env::log(
+            format!(
+                "{} tokens of {} registered as a drop by {}",
+                amount.0, ft_contract_id, sender_id
+            )
+            .as_bytes(),
+        );
+        self.register_drop(
+            drop_msg.public_key.into(),
+            DropInfo {
+                password_hash: drop_msg.password_hash.map(|hash| hash.0),
+                ..DropInfo::new(
+                    vec![Asset::Ft {
+                        ft_contract_id,
+                        amount: amount.0,
+                    }],
+                    sender_id,
+                    drop_msg.expires_at.map(|expires_at| expires_at.0),
+                )
+            },
+            ACCESS_KEY_ALLOWANCE,
+        );
+        PromiseOrValue::Value(U128(0))
+    }
+}
+
+// TODO: Update tests
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use near_sdk::test_utils::{accounts, get_logs};
+    use near_sdk::MockedBlockchain;
+    use near_sdk::{testing_env, Balance, BlockHeight, PublicKey, VMContext};
+
+    use super::*;
+
+    pub struct VMContextBuilder {
+        context: VMContext,
+    }
+
+    impl VMContextBuilder {
+        pub fn new() -> Self {
+            Self {
+                context: VMContext {
+                    current_account_id: "".to_string(),
+                    signer_account_id: "".to_string(),
+                    signer_account_pk: vec![0, 1, 2],
+                    predecessor_account_id: "".to_string(),
+                    input: vec![],
+                    block_index: 0,
+                    epoch_height: 0,
+                    block_timestamp: 0,
+                    account_balance: 0,
+                    account_locked_balance: 0,
+                    storage_usage: 10u64.pow(6),
+                    attached_deposit: 0,
+                    prepaid_gas: 10u64.pow(18),
+                    random_seed: vec![0, 1, 2],
+                    is_view: false,
+                    output_data_receivers: vec![],
+                },
+            }
+        }
+
+        pub fn current_account_id(mut self, account_id: AccountId) -> Self {
+            self.context.current_account_id = account_id;
+            self
+        }
+
+        #[allow(dead_code)]
+        pub fn signer_account_id(mut self, account_id: AccountId) -> Self {
+            self.context.signer_account_id = account_id;
+            self
+        }
+
+        pub fn predecessor_account_id(mut self, account_id: AccountId) -> Self {
+            self.context.predecessor_account_id = account_id;
+            self
+        }
+
+        #[allow(dead_code)]
+        pub fn block_index(mut self, block_index: BlockHeight) -> Self {
+            self.context.block_index = block_index;
+            self
+        }
+
+        pub fn block_timestamp(mut self, block_timestamp: u64) -> Self {
+            self.context.block_timestamp = block_timestamp;
+            self
+        }
+
+        pub fn attached_deposit(mut self, amount: Balance) -> Self {
+            self.context.attached_deposit = amount;
+            self
+        }
+
+        pub fn account_balance(mut self, amount: Balance) -> Self {
+            self.context.account_balance = amount;
+            self
+        }
+
+        #[allow(dead_code)]
+        pub fn account_locked_balance(mut self, amount: Balance) -> Self {
+            self.context.account_locked_balance = amount;
+            self
+        }
+
+        pub fn signer_account_pk(mut self, pk: PublicKey) -> Self {
+            self.context.signer_account_pk = pk;
+            self
+        }
+
+        pub fn random_seed(mut self, seed: Vec<u8>) -> Self {
+            self.context.random_seed = seed;
+            self
+        }
+
+        pub fn finish(self) -> VMContext {
+            self.context
+        }
+    }
+
+    fn linkdrop() -> String {
+        "linkdrop".to_string()
+    }
+
+    fn bob() -> ValidAccountId {
+        accounts(1)
+    }
+
+    #[test]
+    fn test_create_account() {
+        let mut contract = LinkDrop::default();
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let deposit = 1_000_000;
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(deposit)
+            .finish());
+        contract.create_account(bob(), pk.clone());
+        assert!(!contract.public_key_is_claimable(pk));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_create_invalid_account() {
+        let mut contract = LinkDrop::default();
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let deposit = 1_000_000;
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(deposit)
+            .finish());
+        contract.create_account("XYZ".to_string().try_into().unwrap(), pk.clone());
+        assert!(!contract.public_key_is_claimable(pk));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_create_account_and_claim_invalid_account() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let token_id: TokenId = "0".into();
+        // Deposit money to linkdrop contract.
+        let deposit = ACCESS_KEY_ALLOWANCE * 100;
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .attached_deposit(deposit)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send(
+            &mut contract,
+            pk.clone(),
+            nft().try_into().unwrap(),
+            token_id,
+            None,
+        );
+        // Now, send new transaction to link drop contract.
+        let context = VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.clone().into())
+            .account_balance(deposit)
+            .finish();
+        testing_env!(context);
+        let pk2: Base58PublicKey = "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"
+            .try_into()
+            .unwrap();
+        contract.create_account_and_claim("XYZ".to_string().try_into().unwrap(), pk2.clone(), None);
+        assert!(contract.public_key_is_claimable(pk));
+        assert!(!contract.public_key_is_claimable(pk2));
+    }
+
+    #[test]
+    fn test_create_account_and_claim() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let token_id: TokenId = "0".into();
+        // Deposit money to linkdrop contract.
+        let deposit = ACCESS_KEY_ALLOWANCE * 100;
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(deposit)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send(
+            &mut contract,
+            pk.clone(),
+            nft().try_into().unwrap(),
+            token_id,
+            None,
+        );
+        assert!(contract.public_key_is_claimable(pk.clone()));
+        // Now, send new transaction to link drop contract.
+        let context = VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.clone().into())
+            .account_balance(deposit)
+            .attached_deposit(CREATE_SUBACCOUNT_ALLOWANCE + 1)
+            .finish();
+        testing_env!(context);
+        let pk2: Base58PublicKey = "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"
+            .try_into()
+            .unwrap();
+        contract.create_account_and_claim(bob(), pk2.clone(), None);
+        let receipts = created_receipts();
+        assert_eq!(receipts.len(), 2);
+        assert!(receipts[0].contains(r#"receiver_id: "bob""#));
+        assert!(receipts[0].contains("CreateAccount"));
+        assert!(receipts[0].contains("AddKeyWithFullAccess"));
+        assert!(receipts[0].contains("Transfer"));
+        assert!(receipts[1].contains(r#"receiver_id: "linkdrop""#));
+        assert!(receipts[1].contains(&method_name("on_account_created_and_claimed")));
+        // The link key can't claim again until the callbacks.
+        assert!(!contract.public_key_is_claimable(pk.clone()));
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .account_balance(deposit)
+            .finish());
+        let public_key: PublicKey = pk.clone().into();
+        let result = contract.on_claim_account_created(
+            public_key.clone(),
+            bob().into(),
+            linkdrop(),
+            CREATE_SUBACCOUNT_ALLOWANCE + 1,
+            true,
+        );
+        assert_eq!(
+            result,
+            ClaimResult {
+                failed_step: None,
+                refund: U128(0),
+            }
+        );
+        let receipts = created_receipts();
+        assert_eq!(receipts.len(), 2);
+        assert!(receipts[0].contains(r#"receiver_id: "nft""#));
+        assert!(receipts[0].contains(&method_name("nft_transfer")));
+        assert!(receipts[1].contains(r#"receiver_id: "linkdrop""#));
+        assert!(receipts[1].contains(&method_name("update_nft_storage")));
+        assert!(!receipts.iter().any(|receipt| receipt.contains("DeleteKey")));
+        assert!(!contract.public_key_is_claimable(pk));
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .finish());
+        contract.on_asset_transferred(public_key.clone(), 0, true, true);
+        let receipts = created_receipts();
+        assert_eq!(receipts.len(), 1);
+        assert!(receipts[0].contains("DeleteKey"));
+        assert!(receipts[0].contains(&format!("{:?}", public_key)));
+        assert!(!receipts[0].contains(&format!("{:?}", PublicKey::from(pk2))));
+        let drop = contract.find_drop(&public_key).unwrap();
+        assert_eq!(drop.status, DropStatus::Claimed);
+        assert_eq!(drop.new_account_id, Some(bob().into()));
+    }
+
+    #[should_panic(expected = r#"Signer must be eligible to claim the NFT"#)]
+    #[test]
+    fn test_create_account_and_claim_pk_not_claimable() {
+        let mut contract = LinkDrop::default();
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let deposit = ACCESS_KEY_ALLOWANCE * 100;
+        // Now, send new transaction to link drop contract.
+        let context = VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.into())
+            .account_balance(deposit)
+            .attached_deposit(CREATE_SUBACCOUNT_ALLOWANCE + 1)
+            .finish();
+        testing_env!(context);
+        let pk2 = "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"
+            .try_into()
+            .unwrap();
+        contract.create_account_and_claim(bob(), pk2, None);
+        // TODO: verify that proper promises were created.
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Account already registered"#)]
+    fn test_send_two_times() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let token_id: TokenId = "0".into();
+        // Deposit money to linkdrop contract.
+        let deposit = ACCESS_KEY_ALLOWANCE + 1;
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(deposit)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send(
+            &mut contract,
+            pk.clone(),
+            nft().try_into().unwrap(),
+            token_id.clone(),
+            None,
+        );
+        assert!(contract.public_key_is_claimable(pk.clone()));
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(deposit)
+            .finish());
+        send(
+            &mut contract,
+            pk.clone(),
+            nft().try_into().unwrap(),
+            token_id,
+            None,
+        );
+    }
+
+    #[test]
+    fn test_claim() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let token_id: TokenId = "0".into();
+        // Deposit money to linkdrop contract.
+        let deposit = ACCESS_KEY_ALLOWANCE * 100;
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(deposit)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send(
+            &mut contract,
+            pk.clone(),
+            nft().try_into().unwrap(),
+            token_id,
+            None,
+        );
+        assert!(contract.public_key_is_claimable(pk.clone()));
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.clone().into())
+            .attached_deposit(deposit)
+            .finish());
+        contract.claim(bob(), None);
+    }
+
+    #[should_panic(expected = r#"Signer must be eligible to claim the NFT"#)]
+    #[test]
+    fn test_claim_pk_not_claimable() {
+        let mut contract = LinkDrop::default();
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        // Deposit money to linkdrop contract.
+        let deposit = ACCESS_KEY_ALLOWANCE * 100;
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.clone().into())
+            .attached_deposit(deposit)
+            .finish());
+        contract.claim(bob(), None);
+    }
+
+    #[test]
+    fn test_claim_invalid_current_account() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let token_id: TokenId = "0".into();
+        // Deposit money to linkdrop contract.
+        let deposit = ACCESS_KEY_ALLOWANCE * 100;
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(deposit)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send(
+            &mut contract,
+            pk.clone(),
+            nft().try_into().unwrap(),
+            token_id,
+            None,
+        );
+        assert!(contract.public_key_is_claimable(pk.clone()));
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(bob().into())
+            .signer_account_pk(pk.clone().into())
+            .attached_deposit(deposit)
+            .finish());
+        contract.claim(bob(), None);
+    }
+    #[should_panic(expected = r#"The account ID is invalid"#)]
+    #[test]
+    fn test_claim_invalid_account() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let token_id: TokenId = "0".into();
+        // Deposit money to linkdrop contract.
+        let deposit = ACCESS_KEY_ALLOWANCE * 100;
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(deposit)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send(
+            &mut contract,
+            pk.clone(),
+            nft().try_into().unwrap(),
+            token_id,
+            None,
+        );
+        assert!(contract.public_key_is_claimable(pk.clone()));
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_pk(pk.clone().into())
+            .attached_deposit(deposit)
+            .finish());
+        contract.claim("XYZ".to_string().try_into().unwrap(), None);
+    }
+
+    fn nft() -> String {
+        "nft".to_string()
+    }
+
+    fn drop_msg(pk: &str) -> String {
+        format!(r#"{{"public_key": "{}"}}"#, pk)
+    }
+
+    /// Deposits the allowance of `drops` drops for `account_id`, then continues with calls from
+    /// the NFT contract.
+    fn deposit_allowance(contract: &mut LinkDrop, account_id: ValidAccountId, drops: u128) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(account_id.into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * drops)
+            .finish());
+        contract.deposit_allowance();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(nft())
+            .finish());
+    }
+
+    fn unwrap_value<T>(value: PromiseOrValue<T>) -> T {
+        match value {
+            PromiseOrValue::Value(value) => value,
+            PromiseOrValue::Promise(_) => panic!("Expected a value"),
+        }
+    }
+
+    #[test]
+    fn test_nft_on_transfer() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(nft())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        deposit_allowance(&mut contract, bob(), 1);
+        let refund = contract.nft_on_transfer(
+            bob().into(),
+            bob().into(),
+            "0".to_string(),
+            drop_msg("ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"),
+        );
+        assert!(!unwrap_value(refund));
+        assert!(contract.public_key_is_claimable(pk));
+    }
+
+    #[test]
+    fn test_nft_on_transfer_wrong_contract() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        let refund = contract.nft_on_transfer(
+            bob().into(),
+            bob().into(),
+            "0".to_string(),
+            drop_msg("ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"),
+        );
+        assert!(unwrap_value(refund));
+        assert!(!contract.public_key_is_claimable(pk));
+    }
+
+    #[test]
+    fn test_nft_on_transfer_invalid_msg() {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(nft())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        let refund = contract.nft_on_transfer(
+            bob().into(),
+            bob().into(),
+            "0".to_string(),
+            "{}".to_string(),
+        );
+        assert!(unwrap_value(refund));
+        let refund = contract.nft_on_transfer(
+            bob().into(),
+            bob().into(),
+            "0".to_string(),
+            drop_msg("not a key"),
+        );
+        assert!(unwrap_value(refund));
+    }
+
+    #[test]
+    fn test_nft_on_transfer_two_times() {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(nft())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        deposit_allowance(&mut contract, bob(), 1);
+        let msg = drop_msg("ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz");
+        let refund =
+            contract.nft_on_transfer(bob().into(), bob().into(), "0".to_string(), msg.clone());
+        assert!(!unwrap_value(refund));
+        let refund = contract.nft_on_transfer(bob().into(), bob().into(), "1".to_string(), msg);
+        assert!(unwrap_value(refund));
+    }
+
+    #[test]
+    fn test_nft_on_transfer_without_allowance_deposit() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(nft())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        let refund = contract.nft_on_transfer(
+            bob().into(),
+            bob().into(),
+            "0".to_string(),
+            drop_msg("ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"),
+        );
+        assert!(unwrap_value(refund));
+        assert_eq!(get_logs(), vec!["Not enough allowance deposit".to_string()]);
+        assert!(!contract.public_key_is_claimable(pk));
+    }
+
+    #[test]
+    fn test_allowance_deposit() {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        deposit_allowance(&mut contract, bob(), 2);
+        contract.nft_on_transfer(
+            bob().into(),
+            bob().into(),
+            "0".to_string(),
+            drop_msg("ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"),
+        );
+        assert_eq!(
+            contract.get_allowance_deposit(bob()),
+            U128(ACCESS_KEY_ALLOWANCE)
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.withdraw_allowance();
+        let receipts = created_receipts();
+        assert_eq!(receipts.len(), 1);
+        assert!(receipts[0].contains(r#"receiver_id: "bob""#));
+        assert!(receipts[0].contains(&format!("deposit: {}", ACCESS_KEY_ALLOWANCE)));
+        assert_eq!(contract.get_allowance_deposit(bob()), U128(0));
+    }
+
+    #[test]
+    #[should_panic(expected = r#"NFT contract is not whitelisted"#)]
+    fn test_send_not_whitelisted() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send(&mut contract, pk, bob(), "0".to_string(), None);
+    }
+
+    #[test]
+    fn test_whitelist_nft_contracts() {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        assert!(contract.add_nft_contract(bob()));
+        assert!(!contract.add_nft_contract(bob()));
+        assert_eq!(contract.get_nft_contracts(), vec![nft(), bob().into()]);
+        assert!(contract.remove_nft_contract(nft().try_into().unwrap()));
+        assert_eq!(contract.get_nft_contracts(), vec![bob().to_string()]);
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Only the Smart Contract owner can call this method"#)]
+    fn test_add_nft_contract_not_owner() {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(bob().into())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.add_nft_contract(bob());
+    }
+
+    #[test]
+    fn test_migrate() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .finish());
+        let mut accounts: LookupMap<PublicKey, TokenId> = LookupMap::new(0);
+        accounts.insert(&pk.clone().into(), &"0".to_string());
+        env::state_write(&OldLinkDrop {
+            accounts,
+            nft_contract_id: nft(),
+        });
+        let contract = LinkDrop::migrate();
+        assert_eq!(contract.get_nft_contracts(), vec![nft()]);
+        assert!(contract.public_key_is_claimable(pk.clone()));
+        assert_eq!(
+            contract.find_drop(&pk.into()),
+            Some(DropInfo {
+                key_added: true,
+                ..DropInfo::new(
+                    vec![Asset::Nft {
+                        nft_contract_id: nft(),
+                        token_id: "0".to_string(),
+                        approval_id: None,
+                    }],
+                    linkdrop(),
+                    None,
+                )
+            })
+        );
+    }
+
+    fn ft() -> String {
+        "ft".to_string()
+    }
+
+    #[test]
+    fn test_ft_on_transfer() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.add_ft_contract(ft().try_into().unwrap());
+        deposit_allowance(&mut contract, bob(), 1);
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(ft())
+            .finish());
+        let refund = contract.ft_on_transfer(
+            bob(),
+            U128(100),
+            drop_msg("ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"),
+        );
+        assert_eq!(unwrap_value(refund).0, 0);
+        assert_eq!(contract.get_allowance_deposit(bob()), U128(0));
+        assert_eq!(
+            contract.find_drop(&pk.clone().into()),
+            Some(DropInfo::new(
+                vec![Asset::Ft {
+                    ft_contract_id: ft(),
+                    amount: 100,
+                }],
+                bob().into(),
+                None,
+            ))
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(bob(), None);
+    }
+
+    #[test]
+    fn test_ft_on_transfer_not_whitelisted() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(ft())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        let refund = contract.ft_on_transfer(
+            bob(),
+            U128(100),
+            drop_msg("ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"),
+        );
+        assert_eq!(unwrap_value(refund).0, 100);
+        assert!(!contract.public_key_is_claimable(pk));
+    }
+
+    #[test]
+    fn test_ft_on_transfer_rejected() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.add_ft_contract(ft().try_into().unwrap());
+        deposit_allowance(&mut contract, bob(), 1);
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(ft())
+            .finish());
+        let refund = contract.ft_on_transfer(
+            bob(),
+            U128(0),
+            drop_msg("ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"),
+        );
+        assert_eq!(unwrap_value(refund).0, 0);
+        assert_eq!(get_logs(), vec!["Amount must be positive".to_string()]);
+        let refund = contract.ft_on_transfer(
+            accounts(2),
+            U128(100),
+            drop_msg("ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"),
+        );
+        assert_eq!(unwrap_value(refund).0, 100);
+        assert!(get_logs().contains(&"Not enough allowance deposit".to_string()));
+        assert!(!contract.public_key_is_claimable(pk));
+        assert_eq!(
+            contract.get_allowance_deposit(bob()),
+            U128(ACCESS_KEY_ALLOWANCE)
+        );
+    }
+
+    #[test]
+    fn test_send_near() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_near(pk.clone(), None);
+        assert_eq!(
+            contract.find_drop(&pk.clone().into()),
+            Some(DropInfo::new(
+                vec![Asset::Near { amount: 100 }],
+                bob().into(),
+                None
+            ))
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(bob(), None);
+    }
+
+    #[test]
+    fn test_cancel_near_drop() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_near(pk.clone(), None);
+        contract.cancel_drop(pk.clone());
+        assert!(!contract.public_key_is_claimable(pk));
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Only the sender can cancel the drop"#)]
+    fn test_cancel_drop_not_sender() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_near(pk.clone(), None);
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .finish());
+        contract.cancel_drop(pk);
+    }
+
+    fn bundle(contract: &mut LinkDrop, pk: Base58PublicKey) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + 100)
+            .finish());
+        contract.add_ft_contract(ft().try_into().unwrap());
+        let assets = serde_json::from_str(
+            r#"[
+                {"nft": {"nft_contract_id": "nft", "token_id": "0"}},
+                {"nft": {"nft_contract_id": "nft", "token_id": "1"}},
+                {"ft": {"ft_contract_id": "ft", "amount": "10"}}
+            ]"#,
+        )
+        .unwrap();
+        contract.send_bundle(pk, assets, None, None);
+    }
+
+    #[test]
+    fn test_send_bundle() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        bundle(&mut contract, pk.clone());
+        let drop = contract.find_drop(&pk.clone().into()).unwrap();
+        assert_eq!(
+            drop.assets,
+            vec![
+                Asset::Nft {
+                    nft_contract_id: nft(),
+                    token_id: "0".to_string(),
+                    approval_id: None,
+                },
+                Asset::Nft {
+                    nft_contract_id: nft(),
+                    token_id: "1".to_string(),
+                    approval_id: None,
+                },
+                Asset::Ft {
+                    ft_contract_id: ft(),
+                    amount: 10,
+                },
+                Asset::Near { amount: 100 },
+            ]
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(bob(), None);
+    }
+
+    #[test]
+    fn test_bundle_partial_claim() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        bundle(&mut contract, pk.clone());
+        let public_key: PublicKey = pk.clone().into();
+        contract.on_asset_transferred(public_key.clone(), 0, false, true);
+        contract.on_asset_transferred(public_key.clone(), 1, false, false);
+        contract.on_asset_transferred(public_key.clone(), 2, false, true);
+        contract.on_asset_transferred(public_key.clone(), 3, true, true);
+        let drop = contract.find_drop(&public_key).unwrap();
+        assert_eq!(drop.claimed, vec![true, false, true, true]);
+        assert!(contract.public_key_is_claimable(pk.clone()));
+
+        // Retrying only transfers the remaining token.
+        contract.on_asset_transferred(public_key, 1, true, true);
+        assert!(!contract.public_key_is_claimable(pk));
+    }
+
+    #[test]
+    #[should_panic(expected = r#"FT contract is not whitelisted"#)]
+    fn test_send_bundle_not_whitelisted() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_bundle(
+            pk,
+            vec![AssetArgs::Ft {
+                ft_contract_id: ft().try_into().unwrap(),
+                amount: U128(10),
+            }],
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Drop has expired"#)]
+    fn test_claim_expired() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send(
+            &mut contract,
+            pk.clone(),
+            nft().try_into().unwrap(),
+            "0".to_string(),
+            Some(U64(100)),
+        );
+        assert!(contract.public_key_is_claimable(pk.clone()));
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.clone().into())
+            .block_timestamp(100)
+            .finish());
+        assert!(!contract.public_key_is_claimable(pk));
+        contract.claim(bob(), None);
+    }
+
+    #[test]
+    fn test_reclaim() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(nft())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        deposit_allowance(&mut contract, bob(), 1);
+        let refund = contract.nft_on_transfer(
+            bob().into(),
+            bob().into(),
+            "0".to_string(),
+            r#"{"public_key": "ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz", "expires_at": "100"}"#
+                .to_string(),
+        );
+        assert!(!unwrap_value(refund));
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .block_timestamp(100)
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.reclaim(pk.clone());
+        assert_eq!(contract.find_drop(&pk.into()), None);
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Drop has not expired yet"#)]
+    fn test_reclaim_not_expired() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_near(pk.clone(), Some(U64(100)));
+        contract.reclaim(pk);
+    }
+
+    #[test]
+    fn test_cancel_nft_drop() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(nft())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        deposit_allowance(&mut contract, bob(), 1);
+        contract.nft_on_transfer(
+            bob().into(),
+            bob().into(),
+            "0".to_string(),
+            drop_msg("ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"),
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.cancel_drop(pk.clone());
+        assert_eq!(contract.find_drop(&pk.into()), None);
+    }
+
+    #[test]
+    fn test_cancel_keeps_allowance() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + 100)
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_near(pk.clone(), None);
+        add_keys(&mut contract);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.cancel_drop(pk);
+        let receipts = created_receipts();
+        assert_eq!(receipts.len(), 2);
+        assert!(receipts[0].contains("DeleteKey"));
+        assert!(receipts[1].contains(r#"receiver_id: "bob""#));
+        assert!(receipts[1].contains("deposit: 100 "));
+    }
+
+    #[test]
+    fn test_cancel_keeps_key_not_added() {
+        // The full access key of the linkdrop, which a drop must never delete
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + 100)
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_near(pk.clone(), None);
+        // Adding the key fails because the account already has it
+        assert!(!resolve_keys_added(&mut contract, PromiseResult::Failed));
+        assert_eq!(
+            get_logs(),
+            vec!["Failed to add the access keys of the drops".to_string()]
+        );
+        assert!(!contract.find_drop(&pk.clone().into()).unwrap().key_added);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.cancel_drop(pk);
+        let receipts = created_receipts();
+        assert!(!receipts.iter().any(|receipt| receipt.contains("DeleteKey")));
+        assert!(receipts
+            .iter()
+            .any(|receipt| receipt.contains(r#"receiver_id: "bob""#)
+                && receipt.contains("deposit: 100 ")));
+    }
+
+    #[test]
+    fn test_keys_added_after_cancel() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + 100)
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_near(pk.clone(), None);
+        let callback = last_callback("on_keys_added");
+        contract.cancel_drop(pk);
+        assert!(!created_receipts()
+            .iter()
+            .any(|receipt| receipt.contains("DeleteKey")));
+
+        // The key added before the cancellation is deleted by the callback
+        let args: OnKeysAddedArgs = serde_json::from_slice(&receipt_args(&callback)).unwrap();
+        callback_context(PromiseResult::Successful(vec![]));
+        assert!(contract.on_keys_added(args.public_keys));
+        let receipts = created_receipts();
+        assert_eq!(receipts.len(), 1);
+        assert!(receipts[0].contains("DeleteKey"));
+    }
+
+    #[test]
+    fn test_send_allowed_sender() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.add_sender(bob());
+        assert_eq!(contract.get_senders(), vec![AccountId::from(bob())]);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(bob().into())
+            .predecessor_account_id(bob().into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 2)
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.send(
+            pk.clone(),
+            nft().try_into().unwrap(),
+            "0".to_string(),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        assert!(resolve_nft_token(
+            &mut contract,
+            approved_token(bob().into(), 1)
+        ));
+        let drop = contract.find_drop(&pk.clone().into()).unwrap();
+        assert_eq!(drop.sender_id, AccountId::from(bob()));
+        assert!(created_receipts()[0].contains(&key_allowance(ACCESS_KEY_ALLOWANCE * 2)));
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(bob().into())
+            .predecessor_account_id(bob().into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.cancel_drop(pk.clone());
+        assert!(!contract.public_key_is_claimable(pk));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = r#"Only the Smart Contract owner or allowed senders can create drops"#
+    )]
+    fn test_send_not_allowed_sender() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(bob().into())
+            .predecessor_account_id(bob().into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 2)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send(
+            &mut contract,
+            pk,
+            nft().try_into().unwrap(),
+            "0".to_string(),
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Only the Smart Contract owner can call this method"#)]
+    fn test_send_bundle_allowed_sender() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.add_sender(bob());
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(bob().into())
+            .predecessor_account_id(bob().into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 2)
+            .finish());
+        contract.send_bundle(
+            pk,
+            vec![AssetArgs::Nft {
+                nft_contract_id: nft().try_into().unwrap(),
+                token_id: "0".to_string(),
+            }],
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Only the sender can reclaim the drop"#)]
+    fn test_reclaim_not_sender() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_near(pk.clone(), Some(U64(100)));
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .block_timestamp(100)
+            .finish());
+        contract.reclaim(pk);
+    }
+
+    fn many_drops(pks: &[&str]) -> Vec<(Base58PublicKey, TokenId)> {
+        pks.iter()
+            .enumerate()
+            .map(|(index, pk)| ((*pk).try_into().unwrap(), index.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_send_many() {
+        let pks = [
+            "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz",
+            "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca",
+        ];
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit((ACCESS_KEY_ALLOWANCE + 1) * 2)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_many(nft().try_into().unwrap(), many_drops(&pks), None);
+        for (index, pk) in pks.iter().enumerate() {
+            let pk: Base58PublicKey = (*pk).try_into().unwrap();
+            let drop = contract.find_drop(&pk.into()).unwrap();
+            assert_eq!(
+                drop.assets,
+                vec![Asset::Nft {
+                    nft_contract_id: nft(),
+                    token_id: index.to_string(),
+                    approval_id: None,
+                }]
+            );
+        }
+        let receipts = created_receipts();
+        assert_eq!(
+            receipts[0]
+                .matches(&key_allowance(ACCESS_KEY_ALLOWANCE + 1))
+                .count(),
+            pks.len()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Account already registered"#)]
+    fn test_send_many_duplicate() {
+        let pks = [
+            "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz",
+            "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz",
+        ];
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit((ACCESS_KEY_ALLOWANCE + 1) * 2)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_many(nft().try_into().unwrap(), many_drops(&pks), None);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = r#"Attached deposit must be greater than ACCESS_KEY_ALLOWANCE for each drop"#
+    )]
+    fn test_send_many_not_enough_deposit() {
+        let pks = [
+            "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz",
+            "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca",
+        ];
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 2)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_many(nft().try_into().unwrap(), many_drops(&pks), None);
+    }
+
+    #[test]
+    fn test_multi_use() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit((ACCESS_KEY_ALLOWANCE + 1) * 2)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_multi_use(
+            pk.clone(),
+            nft().try_into().unwrap(),
+            vec!["0".to_string(), "1".to_string()],
+            None,
+        );
+        add_keys(&mut contract);
+        assert_eq!(
+            contract
+                .find_drop(&pk.clone().into())
+                .unwrap()
+                .remaining_uses,
+            2
+        );
+
+        for (index, receiver) in [accounts(2), accounts(3)].iter().enumerate() {
+            testing_env!(VMContextBuilder::new()
+                .current_account_id(linkdrop())
+                .predecessor_account_id(linkdrop())
+                .signer_account_pk(pk.clone().into())
+                .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+                .finish());
+            contract.claim(receiver.clone(), None);
+            let receipts = created_receipts();
+            assert_eq!(receipts.len(), 2);
+            assert!(receipts[0].contains(&args_part(&format!(r#""token_id":"{}""#, index))));
+            contract.on_asset_transferred(pk.clone().into(), index, true, true);
+            assert_eq!(
+                get_logs(),
+                vec![event(
+                    "drop_claimed",
+                    &format!(
+                        r#"{{"public_key":"ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz","sender_id":"linkdrop","remaining_uses":"{}"}}"#,
+                        1 - index
+                    )
+                )]
+            );
+        }
+        let receipts = created_receipts();
+        assert_eq!(receipts.len(), 3);
+        assert!(receipts[2].contains("DeleteKey"));
+        let drop = contract.find_drop(&pk.into()).unwrap();
+        assert_eq!(drop.status, DropStatus::Claimed);
+        assert_eq!(drop.remaining_uses, 0);
+    }
+
+    #[test]
+    fn test_multi_use_failed_claim() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit((ACCESS_KEY_ALLOWANCE + 1) * 2)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_multi_use(
+            pk.clone(),
+            nft().try_into().unwrap(),
+            vec!["0".to_string(), "1".to_string()],
+            None,
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .finish());
+        contract.on_asset_transferred(pk.clone().into(), 0, true, false);
+        let drop = contract.find_drop(&pk.clone().into()).unwrap();
+        assert_eq!(drop.remaining_uses, 2);
+        assert_eq!(drop.claimed, vec![false, false]);
+
+        contract.on_asset_transferred(pk.clone().into(), 0, true, true);
+        assert!(created_receipts().is_empty());
+        assert_eq!(
+            contract
+                .find_drop(&pk.clone().into())
+                .unwrap()
+                .remaining_uses,
+            1
+        );
+        assert!(contract.public_key_is_claimable(pk));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = r#"Attached deposit must be greater than ACCESS_KEY_ALLOWANCE for each use"#
+    )]
+    fn test_send_multi_use_not_enough_deposit() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 2)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_multi_use(
+            pk,
+            nft().try_into().unwrap(),
+            vec!["0".to_string(), "1".to_string()],
+            None,
+        );
+    }
+
+    /// Calls `send`, resolves its `nft_token` call with a token owned by the linkdrop and adds
+    /// the key of the drop.
+    fn send(
+        contract: &mut LinkDrop,
+        public_key: Base58PublicKey,
+        nft_contract_id: ValidAccountId,
+        token_id: TokenId,
+        expires_at: Option<U64>,
+    ) {
+        contract.send(
+            public_key,
+            nft_contract_id,
+            token_id,
+            expires_at,
+            None,
+            None,
+            None,
+            None,
+        );
+        resolve_nft_token(contract, owned_token(linkdrop()));
+        add_keys(contract);
+    }
+
+    /// Result of `nft_token` for a token of `owner_id` without approvals.
+    fn owned_token(owner_id: AccountId) -> PromiseResult {
+        PromiseResult::Successful(
+            format!(
+                r#"{{"token_id":"0","owner_id":"{}","metadata":null,"approved_account_ids":{{}}}}"#,
+                owner_id
+            )
+            .into_bytes(),
+        )
+    }
+
+    /// Result of `nft_token` for a token of `owner_id` that the linkdrop is approved for.
+    fn approved_token(owner_id: AccountId, approval_id: u64) -> PromiseResult {
+        PromiseResult::Successful(
+            format!(
+                r#"{{"token_id":"0","owner_id":"{}","metadata":null,"approved_account_ids":{{"{}":{}}}}}"#,
+                owner_id,
+                linkdrop(),
+                approval_id
+            )
+            .into_bytes(),
+        )
+    }
+
+    #[derive(Deserialize)]
+    #[serde(crate = "near_sdk::serde")]
+    struct OnSendNftTokenArgs {
+        public_key: PublicKey,
+        drop: SentDrop,
+    }
+
+    /// Resolves the `nft_token` call of the last `send` with `result`, see `on_send_nft_token`.
+    fn resolve_nft_token(contract: &mut LinkDrop, result: PromiseResult) -> bool {
+        let callback = last_callback("on_send_nft_token");
+        on_send_nft_token(contract, &callback, result)
+    }
+
+    /// Calls `on_send_nft_token` with the arguments of its `callback` receipt, as if
+    /// `nft_token` returned `result`.
+    fn on_send_nft_token(contract: &mut LinkDrop, callback: &str, result: PromiseResult) -> bool {
+        let args: OnSendNftTokenArgs = serde_json::from_slice(&receipt_args(callback)).unwrap();
+        callback_context(result);
+        contract.on_send_nft_token(args.public_key, args.drop)
+    }
+
+    #[derive(Deserialize)]
+    #[serde(crate = "near_sdk::serde")]
+    struct OnKeysAddedArgs {
+        public_keys: Vec<PublicKey>,
+    }
+
+    /// Resolves the last `on_keys_added` callback as if the keys were added.
+    fn add_keys(contract: &mut LinkDrop) {
+        resolve_keys_added(contract, PromiseResult::Successful(vec![]));
+    }
+
+    /// Calls the last `on_keys_added` callback with the `result` of adding the keys.
+    fn resolve_keys_added(contract: &mut LinkDrop, result: PromiseResult) -> bool {
+        let callback = last_callback("on_keys_added");
+        let args: OnKeysAddedArgs = serde_json::from_slice(&receipt_args(&callback)).unwrap();
+        callback_context(result);
+        contract.on_keys_added(args.public_keys)
+    }
+
+    /// Last receipt created since the last `testing_env!` that calls `method` on the linkdrop.
+    fn last_callback(method: &str) -> String {
+        created_receipts()
+            .into_iter()
+            .rev()
+            .find(|receipt| receipt.contains(&method_name(method)))
+            .unwrap_or_else(|| panic!("No {} callback", method))
+    }
+
+    /// Context of a callback receiving `result`, with the signer and balance of the current call.
+    fn callback_context(result: PromiseResult) {
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(linkdrop())
+                .signer_account_id(env::signer_account_id())
+                .predecessor_account_id(linkdrop())
+                .account_balance(env::account_balance() + env::attached_deposit())
+                .finish(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![result]
+        );
+    }
+
+    /// Debug representation of each receipt created since the last `testing_env!`.
+    fn created_receipts() -> Vec<String> {
+        near_sdk::test_utils::get_created_receipts()
+            .iter()
+            .map(|receipt| format!("{:?}", receipt))
+            .collect()
+    }
+
+    /// JSON arguments of the function call in the Debug representation of a receipt.
+    fn receipt_args(receipt: &str) -> Vec<u8> {
+        let start = receipt
+            .find("args: [")
+            .expect("Receipt without a function call")
+            + 7;
+        let end = start + receipt[start..].find(']').unwrap();
+        receipt[start..end]
+            .split(", ")
+            .map(|byte| byte.parse().unwrap())
+            .collect()
+    }
+
+    /// Allowance of an access key added by a receipt.
+    fn key_allowance(allowance: Balance) -> String {
+        format!("allowance: Some({})", allowance)
+    }
+
+    fn method_name(name: &str) -> String {
+        format!("method_name: {:?}", name.as_bytes())
+    }
+
+    /// NEP-297 log of an event with a single data item.
+    fn event(name: &str, data: &str) -> String {
+        format!(
+            r#"EVENT_JSON:{{"standard":"linkdrop","version":"1.1.0","event":"{}","data":[{}]}}"#,
+            name, data
+        )
+    }
+
+    /// Part of the JSON arguments of a function call receipt.
+    fn args_part(json: &str) -> String {
+        let bytes = format!("{:?}", json.as_bytes());
+        bytes[1..bytes.len() - 1].to_string()
+    }
+
+    #[test]
+    fn test_claim_promises() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send(
+            pk.clone(),
+            nft().try_into().unwrap(),
+            "0".to_string(),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        let receipts = created_receipts();
+        assert_eq!(receipts.len(), 2);
+        assert!(receipts[0].contains(r#"receiver_id: "nft""#));
+        assert!(receipts[0].contains(&method_name("nft_token")));
+        assert!(receipts[1].contains(r#"receiver_id: "linkdrop""#));
+        assert!(receipts[1].contains(&method_name("on_send_nft_token")));
+        assert!(!contract.public_key_is_claimable(pk.clone()));
+
+        assert!(resolve_nft_token(&mut contract, owned_token(linkdrop())));
+        let receipts = created_receipts();
+        assert_eq!(receipts.len(), 2);
+        assert!(receipts[0].contains(r#"receiver_id: "linkdrop""#));
+        assert!(receipts[0].contains("AddKeyWithFunctionCall"));
+        assert!(receipts[1].contains(&method_name("on_keys_added")));
+        add_keys(&mut contract);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.clone().into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(bob(), None);
+        let receipts = created_receipts();
+        assert_eq!(receipts.len(), 2);
+        assert!(receipts[0].contains(r#"receiver_id: "nft""#));
+        assert!(receipts[0].contains(&method_name("nft_transfer")));
+        assert!(receipts[1].contains(r#"receiver_id: "linkdrop""#));
+        assert!(receipts[1].contains(&method_name("update_nft_storage")));
+
+        // A failed transfer releases the drop without deleting the key.
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .finish());
+        contract.on_asset_transferred(pk.clone().into(), 0, true, false);
+        assert!(created_receipts().is_empty());
+        assert!(contract.public_key_is_claimable(pk));
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Drop is being claimed"#)]
+    fn test_claim_while_pending() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send(
+            &mut contract,
+            pk.clone(),
+            nft().try_into().unwrap(),
+            "0".to_string(),
+            None,
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(bob(), None);
+        contract.claim(accounts(2), None);
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Drop has already been claimed"#)]
+    fn test_claim_after_claimed() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send(
+            &mut contract,
+            pk.clone(),
+            nft().try_into().unwrap(),
+            "0".to_string(),
+            None,
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.clone().into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(bob(), None);
+        contract.on_asset_transferred(pk.into(), 0, true, true);
+        contract.claim(accounts(2), None);
+    }
+
+    #[test]
+    fn test_create_account_and_claim_failed() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let pk2: Base58PublicKey = "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send(
+            &mut contract,
+            pk.clone(),
+            nft().try_into().unwrap(),
+            "0".to_string(),
+            None,
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.clone().into())
+            .attached_deposit(CREATE_SUBACCOUNT_ALLOWANCE + 1)
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.create_account_and_claim(bob(), pk2, None);
+        contract.on_claim_account_created(pk.clone().into(), bob().into(), linkdrop(), 0, true);
+        contract.on_asset_transferred(pk.clone().into(), 0, true, false);
+        let drop = contract.find_drop(&pk.clone().into()).unwrap();
+        assert_eq!(
+            drop.status,
+            DropStatus::Failed {
+                reason: "Failed to transfer token 0 of nft to bob".to_string()
+            }
+        );
+        assert!(!contract.public_key_is_claimable(pk.clone()));
+
+        // The sender can still take the token back.
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .finish());
+        contract.cancel_drop(pk.clone());
+        assert_eq!(contract.find_drop(&pk.into()), None);
+    }
+
+    #[test]
+    fn test_create_account_and_claim_refund() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let pk2: Base58PublicKey = "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send(
+            &mut contract,
+            pk.clone(),
+            nft().try_into().unwrap(),
+            "0".to_string(),
+            None,
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.clone().into())
+            .attached_deposit(CREATE_SUBACCOUNT_ALLOWANCE + 1)
+            .finish());
+        contract.create_account_and_claim(bob(), pk2, None);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        let result = contract.on_claim_account_created(
+            pk.clone().into(),
+            bob().into(),
+            linkdrop(),
+            CREATE_SUBACCOUNT_ALLOWANCE + 1,
+            false,
+        );
+        assert_eq!(
+            result,
+            ClaimResult {
+                failed_step: Some(ClaimStep::CreateAccount),
+                refund: U128(CREATE_SUBACCOUNT_ALLOWANCE + 1),
+            }
+        );
+        let receipts = created_receipts();
+        assert_eq!(receipts.len(), 1);
+        assert!(receipts[0].contains(r#"receiver_id: "linkdrop""#));
+        assert!(receipts[0].contains("Transfer"));
+        assert!(!receipts[0].contains(&method_name("nft_transfer")));
+        let drop = contract.find_drop(&pk.clone().into()).unwrap();
+        assert_eq!(drop.status, DropStatus::Available);
+        assert_eq!(drop.new_account_id, None);
+        assert!(contract.public_key_is_claimable(pk));
+    }
+
+    fn failed_claim(contract: &mut LinkDrop, pk: Base58PublicKey) {
+        let pk2: Base58PublicKey = "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        send(
+            contract,
+            pk.clone(),
+            nft().try_into().unwrap(),
+            "0".to_string(),
+            None,
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.clone().into())
+            .attached_deposit(CREATE_SUBACCOUNT_ALLOWANCE + 1)
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.create_account_and_claim(bob(), pk2, None);
+        contract.on_claim_account_created(pk.clone().into(), bob().into(), linkdrop(), 0, true);
+        contract.on_asset_transferred(pk.into(), 0, true, false);
+    }
+
+    #[test]
+    fn test_retry_claim() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        failed_claim(&mut contract, pk.clone());
+        assert_eq!(
+            get_logs(),
+            vec![
+                event("account_created", r#"{"account_id":"bob"}"#),
+                event(
+                    "claim_failed",
+                    r#"{"public_key":"ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz","reason":"Failed to transfer token 0 of nft to bob"}"#
+                ),
+            ]
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.retry_claim(pk.clone());
+        let receipts = created_receipts();
+        assert_eq!(receipts.len(), 2);
+        assert!(receipts[0].contains(r#"receiver_id: "nft""#));
+        assert!(receipts[0].contains(&method_name("nft_transfer")));
+        assert!(receipts[0].contains(&args_part(r#""receiver_id":"bob""#)));
+        let public_key: PublicKey = pk.into();
+        assert_eq!(
+            contract.find_drop(&public_key).unwrap().status,
+            DropStatus::Claiming
+        );
+
+        contract.on_asset_transferred(public_key.clone(), 0, true, true);
+        assert_eq!(
+            contract.find_drop(&public_key).unwrap().status,
+            DropStatus::Claimed
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = r#"Only the Smart Contract owner or the sender can call this method"#
+    )]
+    fn test_retry_claim_not_sender() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        failed_claim(&mut contract, pk.clone());
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(bob().into())
+            .predecessor_account_id(bob().into())
+            .finish());
+        contract.retry_claim(pk);
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Only failed claims can be retried"#)]
+    fn test_retry_claim_not_failed() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send(
+            &mut contract,
+            pk.clone(),
+            nft().try_into().unwrap(),
+            "0".to_string(),
+            None,
+        );
+        contract.retry_claim(pk);
+    }
+
+    #[test]
+    fn test_replace_nft() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        failed_claim(&mut contract, pk.clone());
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.replace_nft(pk.clone(), 0, "1".to_string());
+        assert_eq!(
+            contract.find_drop(&pk.clone().into()).unwrap().assets,
+            vec![Asset::Nft {
+                nft_contract_id: nft(),
+                token_id: "1".to_string(),
+                approval_id: None,
+            }]
+        );
+        contract.retry_claim(pk);
+        let receipts = created_receipts();
+        assert!(receipts[0].contains(&args_part(r#""token_id":"1""#)));
+    }
+
+    /// Allows bob to send drops and calls `send` as bob for token "0".
+    fn send_as_sender(pk: Base58PublicKey) -> LinkDrop {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.add_sender(bob());
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(bob().into())
+            .predecessor_account_id(bob().into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + 1)
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.send(
+            pk,
+            nft().try_into().unwrap(),
+            "0".to_string(),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        contract
+    }
+
+    #[test]
+    fn test_send_approved_token() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let mut contract = send_as_sender(pk.clone());
+        assert!(resolve_nft_token(
+            &mut contract,
+            approved_token(bob().into(), 1)
+        ));
+        assert_eq!(
+            contract.find_drop(&pk.clone().into()).unwrap().assets,
+            vec![Asset::Nft {
+                nft_contract_id: nft(),
+                token_id: "0".to_string(),
+                approval_id: Some(1),
+            }]
+        );
+        assert!(contract.public_key_is_claimable(pk));
+    }
+
+    #[test]
+    fn test_send_approved_token_of_another_owner() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let mut contract = send_as_sender(pk.clone());
+        assert!(!resolve_nft_token(
+            &mut contract,
+            approved_token(accounts(2).into(), 1)
+        ));
+        assert_eq!(
+            get_logs(),
+            vec!["Only the owner of an approved token can send it".to_string()]
+        );
+        assert_eq!(contract.find_drop(&pk.into()), None);
+    }
+
+    #[test]
+    fn test_send_held_token_not_owner() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let mut contract = send_as_sender(pk.clone());
+        assert!(!resolve_nft_token(&mut contract, owned_token(linkdrop())));
+        assert_eq!(
+            get_logs(),
+            vec!["Only the owner can send tokens held by the linkdrop".to_string()]
+        );
+        assert_eq!(contract.find_drop(&pk.into()), None);
+    }
+
+    #[test]
+    fn test_send_token_not_owned() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let mut contract = send_as_sender(pk.clone());
+        assert!(!resolve_nft_token(&mut contract, owned_token(bob().into())));
+        assert_eq!(
+            get_logs(),
+            vec!["Linkdrop is neither the owner nor approved for the token".to_string()]
+        );
+        let receipts = created_receipts();
+        assert_eq!(receipts.len(), 1);
+        assert!(receipts[0].contains(r#"receiver_id: "bob""#));
+        assert!(receipts[0].contains("Transfer"));
+        assert_eq!(contract.find_drop(&pk.into()), None);
+    }
+
+    #[test]
+    fn test_send_token_not_found() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let mut contract = send_as_sender(pk.clone());
+        assert!(!resolve_nft_token(
+            &mut contract,
+            PromiseResult::Successful(b"null".to_vec())
+        ));
+        assert_eq!(get_logs(), vec!["Token not found".to_string()]);
+        assert!(!contract.public_key_is_claimable(pk.clone()));
+
+        let mut contract = send_as_sender(pk.clone());
+        assert!(!resolve_nft_token(&mut contract, PromiseResult::Failed));
+        assert_eq!(get_logs(), vec!["Token not found".to_string()]);
+        assert!(!contract.public_key_is_claimable(pk));
+    }
+
+    fn approve(contract: &mut LinkDrop) -> PromiseOrValue<String> {
+        deposit_allowance(contract, bob(), 1);
+        contract.nft_on_approve(
+            "0".to_string(),
+            bob().into(),
+            7,
+            drop_msg("ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"),
+        )
+    }
+
+    #[test]
+    fn test_nft_on_approve() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        assert_eq!(unwrap_value(approve(&mut contract)), "registered");
+        let drop = contract.find_drop(&pk.clone().into()).unwrap();
+        assert_eq!(
+            drop.assets,
+            vec![Asset::Nft {
+                nft_contract_id: nft(),
+                token_id: "0".to_string(),
+                approval_id: Some(7),
+            }]
+        );
+        assert_eq!(drop.sender_id, bob().to_string());
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(accounts(2), None);
+        let receipts = created_receipts();
+        assert!(receipts[0].contains(&method_name("nft_transfer")));
+        assert!(receipts[0].contains(&args_part(r#""approval_id":7"#)));
+    }
+
+    #[test]
+    fn test_nft_on_approve_not_whitelisted() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(ft());
+        assert_eq!(unwrap_value(approve(&mut contract)), "rejected");
+        assert!(!contract.public_key_is_claimable(pk));
+    }
+
+    #[test]
+    fn test_nft_on_approve_without_allowance_deposit() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(nft())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        let result = contract.nft_on_approve(
+            "0".to_string(),
+            bob().into(),
+            7,
+            drop_msg("ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"),
+        );
+        assert_eq!(unwrap_value(result), "rejected");
+        assert_eq!(get_logs(), vec!["Not enough allowance deposit".to_string()]);
+        assert!(!contract.public_key_is_claimable(pk));
+    }
+
+    #[test]
+    fn test_claim_revoked_approval() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        approve(&mut contract);
+        add_keys(&mut contract);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.clone().into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(accounts(2), None);
+        contract.on_asset_transferred(pk.clone().into(), 0, true, false);
+        assert_eq!(
+            get_logs(),
+            vec![event(
+                "claim_failed",
+                r#"{"public_key":"ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz","reason":"Failed to transfer token 0 of nft, the approval may have been revoked"}"#
+            )]
+        );
+        assert!(!contract.public_key_is_claimable(pk.clone()));
+
+        // Cancelling only deletes the key, the token never left its owner.
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.cancel_drop(pk);
+        let receipts = created_receipts();
+        assert_eq!(receipts.len(), 1);
+        assert!(receipts[0].contains("DeleteKey"));
+        assert!(!receipts[0].contains(&method_name("nft_transfer")));
+    }
+
+    #[test]
+    fn test_drop_created_event() {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(nft())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        deposit_allowance(&mut contract, bob(), 1);
+        contract.nft_on_transfer(
+            bob().into(),
+            bob().into(),
+            "0".to_string(),
+            drop_msg("ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"),
+        );
+        assert_eq!(
+            get_logs(),
+            vec![
+                "Token 0 of bob registered as a drop by bob".to_string(),
+                event(
+                    "drop_created",
+                    r#"{"public_key":"ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz","sender_id":"bob"}"#
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_drop_claimed_event() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_near(pk.clone(), None);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.clone().into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(accounts(2), None);
+        contract.on_asset_transferred(pk.into(), 0, true, true);
+        assert_eq!(
+            get_logs(),
+            vec![event(
+                "drop_claimed",
+                r#"{"public_key":"ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz","sender_id":"bob","remaining_uses":"0"}"#
+            )]
+        );
+    }
+
+    #[test]
+    fn test_claim_failed_event() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_near(pk.clone(), None);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.clone().into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(accounts(2), None);
+        contract.on_asset_transferred(pk.into(), 0, true, false);
+        assert_eq!(
+            get_logs(),
+            vec![event(
+                "claim_failed",
+                r#"{"public_key":"ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz","reason":"Some assets couldn't be transferred"}"#
+            )]
+        );
+    }
+
+    #[test]
+    fn test_account_created_events() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let pk2: Base58PublicKey = "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_near(pk.clone(), None);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.clone().into())
+            .attached_deposit(CREATE_SUBACCOUNT_ALLOWANCE + 1)
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.create_account_and_claim(accounts(2), pk2.clone(), None);
+        contract.on_claim_account_created(
+            pk.clone().into(),
+            accounts(2).into(),
+            linkdrop(),
+            0,
+            false,
+        );
+        contract.create_account_and_claim(accounts(2), pk2, None);
+        contract.on_claim_account_created(pk.into(), accounts(2).into(), linkdrop(), 0, true);
+        assert_eq!(
+            get_logs(),
+            vec![
+                event(
+                    "claim_failed",
+                    r#"{"public_key":"ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz","reason":"Failed to create account charlie"}"#
+                ),
+                event("account_created", r#"{"account_id":"charlie"}"#),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_drop_cancelled_and_expired_events() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let pk2: Base58PublicKey = "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_near(pk.clone(), None);
+        contract.send_near(pk2.clone(), Some(U64(100)));
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .block_timestamp(100)
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.cancel_drop(pk);
+        contract.reclaim(pk2);
+        assert_eq!(
+            get_logs(),
+            vec![
+                event(
+                    "drop_cancelled",
+                    r#"{"public_key":"ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz","sender_id":"bob"}"#
+                ),
+                event(
+                    "drop_expired",
+                    r#"{"public_key":"ed25519:2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca","sender_id":"bob"}"#
+                ),
+            ]
+        );
+    }
+
+    fn view_keys(views: Vec<DropView>) -> Vec<PublicKey> {
+        views
+            .into_iter()
+            .map(|view| view.public_key.into())
+            .collect()
+    }
+
+    #[test]
+    fn test_get_drops() {
+        let pks: Vec<Base58PublicKey> = vec![
+            "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz",
+            "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca",
+            "EdqM52SpXCn5c1uozuvuH5o9Tcr41kYeCWz4Ymu6ngbt",
+        ]
+        .into_iter()
+        .map(|pk| pk.try_into().unwrap())
+        .collect();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + 100)
+            .block_timestamp(10)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_near(pks[0].clone(), Some(U64(100)));
+        contract.send_near(pks[1].clone(), None);
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(accounts(2).into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + 100)
+            .finish());
+        contract.send_near(pks[2].clone(), None);
+
+        assert_eq!(contract.get_drop_count(), U64(3));
+        let drop = contract.get_drop(pks[0].clone()).unwrap();
+        assert_eq!(drop.assets, vec![AssetView::Near { amount: U128(100) }]);
+        assert_eq!(drop.sender_id, bob().to_string());
+        assert_eq!(drop.status, DropStatus::Available);
+        assert_eq!(drop.expires_at, Some(U64(100)));
+        let unknown: Base58PublicKey = "5Ba2vn7EcuaYvrhJBtUPZu8BYGFwNKjJwG8xFYskpme4"
+            .try_into()
+            .unwrap();
+        assert!(contract.get_drop(unknown).is_none());
+
+        let all: Vec<PublicKey> = pks.iter().cloned().map(PublicKey::from).collect();
+        assert_eq!(view_keys(contract.get_drops(None, None)), all);
+        assert_eq!(
+            view_keys(contract.get_drops(Some(U128(1)), Some(1))),
+            vec![all[1].clone()]
+        );
+        assert_eq!(
+            view_keys(contract.get_drops(Some(U128(5)), None)),
+            Vec::<PublicKey>::new()
+        );
+        assert_eq!(
+            view_keys(contract.get_drops_for_sender(bob(), None, None)),
+            vec![all[0].clone(), all[1].clone()]
+        );
+        assert_eq!(
+            view_keys(contract.get_drops_for_sender(bob(), Some(U128(1)), Some(5))),
+            vec![all[1].clone()]
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(accounts(2).into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.cancel_drop(pks[2].clone());
+        assert_eq!(contract.get_drop_count(), U64(2));
+        assert_eq!(
+            view_keys(contract.get_drops_for_sender(accounts(2), None, None)),
+            Vec::<PublicKey>::new()
+        );
+    }
+
+    fn drop_key_by_token(contract: &LinkDrop, token_id: &str) -> Option<PublicKey> {
+        contract
+            .get_drop_by_token(nft().try_into().unwrap(), token_id.to_string())
+            .map(|view| view.public_key.into())
+    }
+
+    #[test]
+    fn test_get_drop_by_token() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send(
+            &mut contract,
+            pk.clone(),
+            nft().try_into().unwrap(),
+            "0".to_string(),
+            None,
+        );
+        assert_eq!(drop_key_by_token(&contract, "0"), Some(pk.clone().into()));
+        assert_eq!(drop_key_by_token(&contract, "1"), None);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.clone().into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(bob(), None);
+        contract.on_asset_transferred(pk.into(), 0, true, true);
+        assert_eq!(drop_key_by_token(&contract, "0"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Token is already in a drop"#)]
+    fn test_send_same_token_twice() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let pk2: Base58PublicKey = "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send(
+            &mut contract,
+            pk,
+            nft().try_into().unwrap(),
+            "0".to_string(),
+            None,
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.send(
+            pk2,
+            nft().try_into().unwrap(),
+            "0".to_string(),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    fn test_send_same_token_while_verifying() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let pk2: Base58PublicKey = "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + 1)
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        for pk in [pk.clone(), pk2.clone()].iter() {
+            contract.send(
+                pk.clone(),
+                nft().try_into().unwrap(),
+                "0".to_string(),
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+        }
+        let callbacks: Vec<String> = created_receipts()
+            .into_iter()
+            .filter(|receipt| receipt.contains(&method_name("on_send_nft_token")))
+            .collect();
+        assert!(on_send_nft_token(
+            &mut contract,
+            &callbacks[0],
+            owned_token(linkdrop())
+        ));
+        assert!(!on_send_nft_token(
+            &mut contract,
+            &callbacks[1],
+            owned_token(linkdrop())
+        ));
+        assert!(get_logs().contains(&"Token is already in a drop".to_string()));
+        assert_eq!(contract.find_drop(&pk2.into()), None);
+    }
+
+    #[test]
+    fn test_cancel_clears_token() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(nft())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        deposit_allowance(&mut contract, bob(), 1);
+        contract.nft_on_transfer(
+            bob().into(),
+            bob().into(),
+            "0".to_string(),
+            drop_msg("ed25519:qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"),
+        );
+        assert!(unwrap_value(contract.nft_on_transfer(
+            bob().into(),
+            bob().into(),
+            "0".to_string(),
+            drop_msg("ed25519:2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"),
+        )));
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.cancel_drop(pk);
+        assert_eq!(drop_key_by_token(&contract, "0"), None);
+    }
+
+    fn create_campaign(contract: &mut LinkDrop) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .finish());
+        contract.create_campaign(
+            "launch".to_string(),
+            CampaignArgs {
+                owner_id: bob(),
+                nft_contract_id: nft().try_into().unwrap(),
+                expires_at: Some(U64(1000)),
+                max_claims: Some(U64(1)),
+                memo: Some("Launch party".to_string()),
+                metadata: None,
+            },
+        );
+    }
+
+    fn send_campaign_drop(
+        contract: &mut LinkDrop,
+        pk: Base58PublicKey,
+        token_id: &str,
+        memo: Option<String>,
+    ) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 2)
+            .finish());
+        contract.send(
+            pk,
+            nft().try_into().unwrap(),
+            token_id.to_string(),
+            None,
+            Some("launch".to_string()),
+            memo,
+            None,
+            None,
+        );
+        let receipts = created_receipts();
+        assert!(receipts[1].contains(&args_part(r#""expires_at":"1000""#)));
+        resolve_nft_token(contract, owned_token(linkdrop()));
+    }
+
+    fn campaign_counts(contract: &LinkDrop) -> (U64, U64, U64) {
+        let campaign = contract.get_campaign("launch".to_string()).unwrap();
+        (campaign.created, campaign.claimed, campaign.outstanding)
+    }
+
+    #[test]
+    fn test_campaign() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let pk2: Base58PublicKey = "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        create_campaign(&mut contract);
+        send_campaign_drop(&mut contract, pk.clone(), "0", None);
+        send_campaign_drop(&mut contract, pk2.clone(), "1", None);
+        assert_eq!(campaign_counts(&contract), (U64(2), U64(0), U64(2)));
+        let drop = contract.find_drop(&pk.clone().into()).unwrap();
+        assert_eq!(drop.campaign_id, Some("launch".to_string()));
+        assert_eq!(drop.expires_at, Some(1000));
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.clone().into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(accounts(2), None);
+        contract.on_asset_transferred(pk.into(), 0, true, true);
+        assert_eq!(campaign_counts(&contract), (U64(2), U64(1), U64(1)));
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.cancel_drop(pk2);
+        assert_eq!(campaign_counts(&contract), (U64(2), U64(1), U64(0)));
+        assert_eq!(contract.get_campaigns(None, None).len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Campaign has no claims left"#)]
+    fn test_campaign_max_claims() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let pk2: Base58PublicKey = "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        create_campaign(&mut contract);
+        send_campaign_drop(&mut contract, pk.clone(), "0", None);
+        send_campaign_drop(&mut contract, pk2.clone(), "1", None);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.clone().into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(accounts(2), None);
+        contract.on_asset_transferred(pk.into(), 0, true, true);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk2.into())
+            .finish());
+        contract.claim(accounts(3), None);
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Campaign has no claims left"#)]
+    fn test_campaign_max_claims_while_claiming() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let pk2: Base58PublicKey = "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        create_campaign(&mut contract);
+        send_campaign_drop(&mut contract, pk.clone(), "0", None);
+        send_campaign_drop(&mut contract, pk2.clone(), "1", None);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(accounts(2), None);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk2.into())
+            .finish());
+        contract.claim(accounts(3), None);
+    }
+
+    #[test]
+    fn test_campaign_failed_claim_releases_max_claims() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let pk2: Base58PublicKey = "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        create_campaign(&mut contract);
+        send_campaign_drop(&mut contract, pk.clone(), "0", None);
+        send_campaign_drop(&mut contract, pk2.clone(), "1", None);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.clone().into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(accounts(2), None);
+        contract.on_asset_transferred(pk.clone().into(), 0, true, false);
+        assert!(contract.public_key_is_claimable(pk));
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk2.clone().into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(accounts(3), None);
+        contract.on_asset_transferred(pk2.into(), 0, true, true);
+        assert_eq!(campaign_counts(&contract), (U64(2), U64(1), U64(1)));
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Only the campaign owner can send drops of the campaign"#)]
+    fn test_send_campaign_not_owner() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        create_campaign(&mut contract);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(accounts(2).into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 2)
+            .finish());
+        contract.send(
+            pk,
+            nft().try_into().unwrap(),
+            "0".to_string(),
+            None,
+            Some("launch".to_string()),
+            None,
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    fn test_claim_memo() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let pk2: Base58PublicKey = "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        create_campaign(&mut contract);
+        send_campaign_drop(&mut contract, pk.clone(), "0", None);
+        send_campaign_drop(
+            &mut contract,
+            pk2.clone(),
+            "1",
+            Some("Claimed from {campaign} at {claimed_at}".to_string()),
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.clone().into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(accounts(2), None);
+        assert!(created_receipts()[0].contains(&args_part(r#""memo":"Launch party""#)));
+        // The failed transfer frees the only claim of the campaign for the second drop
+        contract.on_asset_transferred(pk.into(), 0, true, false);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk2.into())
+            .block_timestamp(500)
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(accounts(3), None);
+        assert!(
+            created_receipts()[0].contains(&args_part(r#""memo":"Claimed from launch at 500""#))
+        );
+    }
+
+    fn send_pool(contract: &mut LinkDrop, pk: Base58PublicKey, random: bool, uses: u64) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit((ACCESS_KEY_ALLOWANCE + 1) * u128::from(uses))
+            .finish());
+        contract.create_pool("pool".to_string(), nft().try_into().unwrap(), random);
+        contract.add_pool_tokens(
+            "pool".to_string(),
+            vec!["0".to_string(), "1".to_string(), "2".to_string()],
+        );
+        contract.send_pool(pk, "pool".to_string(), Some(uses), None);
+    }
+
+    fn claim_pool_token(contract: &mut LinkDrop, pk: Base58PublicKey, seed: Vec<u8>) -> String {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.into())
+            .random_seed(seed)
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(bob(), None);
+        created_receipts().remove(0)
+    }
+
+    fn pool_available(contract: &LinkDrop) -> U64 {
+        contract.get_pool("pool".to_string()).unwrap().available
+    }
+
+    #[test]
+    fn test_pool() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send_pool(&mut contract, pk.clone(), false, 2);
+        assert_eq!(pool_available(&contract), U64(3));
+        assert_eq!(
+            contract.get_drop(pk.clone()).unwrap().assets,
+            vec![AssetView::Pool, AssetView::Pool]
+        );
+
+        let receipt = claim_pool_token(&mut contract, pk.clone(), vec![0]);
+        assert!(receipt.contains(&args_part(r#""token_id":"0""#)));
+        assert_eq!(pool_available(&contract), U64(2));
+        assert_eq!(drop_key_by_token(&contract, "0"), Some(pk.clone().into()));
+        contract.on_asset_transferred(pk.clone().into(), 0, true, true);
+
+        let receipt = claim_pool_token(&mut contract, pk.clone(), vec![0]);
+        assert!(receipt.contains(&args_part(r#""token_id":"1""#)));
+        contract.on_asset_transferred(pk.clone().into(), 1, true, true);
+        assert_eq!(contract.get_drop(pk).unwrap().status, DropStatus::Claimed);
+        assert_eq!(pool_available(&contract), U64(1));
+    }
+
+    #[test]
+    fn test_pool_random() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send_pool(&mut contract, pk.clone(), true, 1);
+        let receipt = claim_pool_token(&mut contract, pk, vec![2]);
+        assert!(receipt.contains(&args_part(r#""token_id":"2""#)));
+        assert_eq!(pool_available(&contract), U64(2));
+    }
 
-        pub fn signer_account_pk(mut self, pk: PublicKey) -> Self {
-            self.context.signer_account_pk = pk;
-            self
-        }
+    #[test]
+    fn test_pool_failed_transfer() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send_pool(&mut contract, pk.clone(), false, 1);
+        claim_pool_token(&mut contract, pk.clone(), vec![0]);
+        contract.on_asset_transferred(pk.clone().into(), 0, true, false);
+        assert_eq!(pool_available(&contract), U64(3));
+        assert_eq!(drop_key_by_token(&contract, "0"), None);
+        assert!(contract.token_in_drop(&nft(), &"0".to_string()));
+        let drop = contract.find_drop(&pk.clone().into()).unwrap();
+        assert_eq!(drop.assets, vec![Asset::Pool]);
+        assert_eq!(drop.status, DropStatus::Available);
 
-        pub fn finish(self) -> VMContext {
-            self.context
-        }
+        // The failed token was put back after the others.
+        let receipt = claim_pool_token(&mut contract, pk, vec![0]);
+        assert!(receipt.contains(&args_part(r#""token_id":"1""#)));
     }
 
-    fn linkdrop() -> String {
-        "linkdrop".to_string()
+    #[test]
+    #[should_panic(expected = r#"Pool is empty"#)]
+    fn test_pool_empty() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 2)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.create_pool("pool".to_string(), nft().try_into().unwrap(), false);
+        contract.send_pool(pk.clone(), "pool".to_string(), None, None);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.into())
+            .finish());
+        contract.claim(bob(), None);
     }
 
-    fn bob() -> ValidAccountId {
-        accounts(1)
+    #[test]
+    #[should_panic(expected = r#"Token is already in a drop"#)]
+    fn test_send_pooled_token() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
+        let pk2: Base58PublicKey = "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"
+            .try_into()
+            .unwrap();
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send_pool(&mut contract, pk, false, 1);
+        contract.send(
+            pk2,
+            nft().try_into().unwrap(),
+            "0".to_string(),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
     }
 
     #[test]
-    fn test_create_account() {
-        let mut contract = LinkDrop::default();
+    #[should_panic(expected = r#"Token is already in a drop"#)]
+    fn test_send_many_pooled_token() {
         let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
             .try_into()
             .unwrap();
-        let deposit = 1_000_000;
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send_pool(&mut contract, pk, false, 1);
+        contract.send_many(
+            nft().try_into().unwrap(),
+            many_drops(&["2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"]),
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Token is already in a pool"#)]
+    fn test_add_pool_tokens_repeated() {
         testing_env!(VMContextBuilder::new()
             .current_account_id(linkdrop())
             .signer_account_id(linkdrop())
-            .attached_deposit(deposit)
+            .predecessor_account_id(linkdrop())
             .finish());
-        contract.create_account(bob(), pk.clone());
-        assert_eq!(contract.public_key_is_claimable(pk), false);
+        let mut contract = LinkDrop::init(nft());
+        contract.create_pool("pool".to_string(), nft().try_into().unwrap(), false);
+        contract.add_pool_tokens("pool".to_string(), vec!["0".to_string(), "0".to_string()]);
     }
 
     #[test]
-    #[should_panic]
-    fn test_create_invalid_account() {
-        let mut contract = LinkDrop::default();
+    #[should_panic(expected = r#"Only the Smart Contract owner can call this method"#)]
+    fn test_send_pool_not_owner() {
         let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
             .try_into()
             .unwrap();
-        let deposit = 1_000_000;
         testing_env!(VMContextBuilder::new()
             .current_account_id(linkdrop())
             .signer_account_id(linkdrop())
-            .attached_deposit(deposit)
+            .predecessor_account_id(linkdrop())
             .finish());
-        contract.create_account("XYZ".to_string().try_into().unwrap(), pk.clone());
-        assert_eq!(contract.public_key_is_claimable(pk), false);
+        let mut contract = LinkDrop::init(nft());
+        contract.create_pool("pool".to_string(), nft().try_into().unwrap(), false);
+        contract.add_pool_tokens("pool".to_string(), vec!["0".to_string()]);
+        contract.add_sender(bob());
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(bob().into())
+            .predecessor_account_id(bob().into())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 2)
+            .finish());
+        contract.send_pool(pk, "pool".to_string(), None, None);
+    }
+
+    fn send_mint(contract: &mut LinkDrop, pk: Base58PublicKey, uses: u64) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .attached_deposit((ACCESS_KEY_ALLOWANCE + MINT_STORAGE_DEPOSIT + 1) * u128::from(uses))
+            .finish());
+        contract.add_sender(bob());
+        contract.send_mint(
+            pk,
+            nft().try_into().unwrap(),
+            "ticket".to_string(),
+            serde_json::from_str(r#"{"title": "Ticket {token_id}"}"#).unwrap(),
+            Some(uses),
+            None,
+        );
     }
 
     #[test]
-    #[should_panic]
-    fn test_create_account_and_claim_invalid_account() {
-        let mut contract = LinkDrop::default();
+    fn test_send_mint() {
         let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
             .try_into()
             .unwrap();
-        let token_id: TokenId = "0".try_into().unwrap();
-        // Deposit money to linkdrop contract.
-        let deposit = ACCESS_KEY_ALLOWANCE * 100;
         testing_env!(VMContextBuilder::new()
             .current_account_id(linkdrop())
-            .attached_deposit(deposit)
             .finish());
-        contract.send(pk.clone(), token_id);
-        // Now, send new transaction to link drop contract.
-        let context = VMContextBuilder::new()
+        let mut contract = LinkDrop::init(nft());
+        send_mint(&mut contract, pk.clone(), 2);
+        let drop = contract.find_drop(&pk.clone().into()).unwrap();
+        assert!(created_receipts()[0].contains(&key_allowance((ACCESS_KEY_ALLOWANCE + 1) * 2)));
+        let token_id = match &drop.assets[1] {
+            Asset::Mint {
+                token_id,
+                token_metadata,
+                deposit,
+                ..
+            } => {
+                assert!(token_id.starts_with("ticket:") && token_id.ends_with(":1"));
+                assert_eq!(token_metadata.title, Some(format!("Ticket {}", token_id)));
+                assert_eq!(*deposit, MINT_STORAGE_DEPOSIT);
+                token_id.clone()
+            }
+            asset => panic!("Unexpected asset {:?}", asset),
+        };
+
+        testing_env!(VMContextBuilder::new()
             .current_account_id(linkdrop())
             .predecessor_account_id(linkdrop())
             .signer_account_pk(pk.clone().into())
-            .account_balance(deposit)
-            .finish();
-        testing_env!(context);
-        let pk2: Base58PublicKey = "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"
-            .try_into()
-            .unwrap();
-        contract.create_account_and_claim("XYZ".to_string().try_into().unwrap(), pk2.clone());
-        assert_eq!(contract.public_key_is_claimable(pk), true);
-        assert_eq!(contract.public_key_is_claimable(pk2), false);
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(accounts(2), None);
+        contract.on_asset_transferred(pk.clone().into(), 0, true, true);
+        contract.claim(accounts(3), None);
+        let receipts = created_receipts();
+        assert!(receipts[2].contains(&method_name("nft_mint")));
+        assert!(receipts[2].contains(&args_part(&format!(r#""token_id":"{}""#, token_id))));
+        assert!(receipts[2].contains(&args_part(r#""receiver_id":"danny""#)));
     }
 
     #[test]
-    fn test_create_account_and_claim() {
-        let mut contract = LinkDrop::default();
+    fn test_send_mint_quoted_series_id() {
         let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
             .try_into()
             .unwrap();
-        let token_id: TokenId = "0".try_into().unwrap();
-        // Deposit money to linkdrop contract.
-        let deposit = ACCESS_KEY_ALLOWANCE * 100;
         testing_env!(VMContextBuilder::new()
             .current_account_id(linkdrop())
             .signer_account_id(linkdrop())
-            .attached_deposit(deposit)
-            .finish());
-        contract.send(pk.clone(), token_id);
-        assert_eq!(contract.public_key_is_claimable(pk.clone()), true);
-        // Now, send new transaction to link drop contract.
-        let context = VMContextBuilder::new()
-            .current_account_id(linkdrop())
             .predecessor_account_id(linkdrop())
-            .signer_account_pk(pk.into())
-            .account_balance(deposit)
-            .finish();
-        testing_env!(context);
-        let pk2 = "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + MINT_STORAGE_DEPOSIT + 1)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_mint(
+            pk.clone(),
+            nft().try_into().unwrap(),
+            r#"ticket","description":"forged"#.to_string(),
+            serde_json::from_str(r#"{"title": "Ticket {token_id}", "description": null}"#).unwrap(),
+            None,
+            None,
+        );
+        match &contract.find_drop(&pk.into()).unwrap().assets[0] {
+            Asset::Mint {
+                token_id,
+                token_metadata,
+                ..
+            } => {
+                assert!(token_id.starts_with(r#"ticket","description":"forged:"#));
+                assert_eq!(token_metadata.title, Some(format!("Ticket {}", token_id)));
+                assert_eq!(token_metadata.description, None);
+            }
+            asset => panic!("Unexpected asset {:?}", asset),
+        }
+    }
+
+    #[test]
+    fn test_cancel_mint_drop() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
             .try_into()
             .unwrap();
-        contract.create_account_and_claim(bob(), pk2);
-        // TODO: verify that proper promises were created.
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        send_mint(&mut contract, pk.clone(), 1);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob().into())
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.cancel_drop(pk);
+        let receipts = created_receipts();
+        assert!(!receipts
+            .iter()
+            .any(|receipt| receipt.contains(&method_name("nft_mint"))));
+        assert!(receipts[1].contains(r#"receiver_id: "bob""#));
+        assert!(receipts[1].contains(&format!("deposit: {}", MINT_STORAGE_DEPOSIT)));
     }
 
-    #[should_panic(expected = r#"Signer must be eligible to claim the NFT"#)]
     #[test]
-    fn test_create_account_and_claim_pk_not_claimable() {
-        let mut contract = LinkDrop::default();
+    #[should_panic(
+        expected = r#"Attached deposit must cover ACCESS_KEY_ALLOWANCE and MINT_STORAGE_DEPOSIT for each use"#
+    )]
+    fn test_send_mint_not_enough_deposit() {
         let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
             .try_into()
             .unwrap();
-        let deposit = ACCESS_KEY_ALLOWANCE * 100;
-        // Now, send new transaction to link drop contract.
-        let context = VMContextBuilder::new()
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + MINT_STORAGE_DEPOSIT)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_mint(
+            pk,
+            nft().try_into().unwrap(),
+            "ticket".to_string(),
+            serde_json::from_str("{}").unwrap(),
+            None,
+            None,
+        );
+    }
+
+    fn send_protected(contract: &mut LinkDrop, pk: Base58PublicKey) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + 100)
+            .finish());
+        let password_hash = LinkDrop::hash_password(&pk.clone().into(), &bob().into(), "secret");
+        contract.send_bundle(pk, vec![], None, Some(Base64VecU8(password_hash)));
+    }
+
+    fn claim_protected(
+        contract: &mut LinkDrop,
+        pk: Base58PublicKey,
+        receiver_id: ValidAccountId,
+        password: Option<&str>,
+    ) {
+        testing_env!(VMContextBuilder::new()
             .current_account_id(linkdrop())
             .predecessor_account_id(linkdrop())
             .signer_account_pk(pk.into())
-            .account_balance(deposit)
-            .finish();
-        testing_env!(context);
-        let pk2 = "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"
+            .account_balance(ACCESS_KEY_ALLOWANCE * 100)
+            .finish());
+        contract.claim(receiver_id, password.map(str::to_string));
+    }
+
+    #[test]
+    fn test_hash_password() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
             .try_into()
             .unwrap();
-        contract.create_account_and_claim(bob(), pk2);
-        // TODO: verify that proper promises were created.
+        testing_env!(VMContextBuilder::new().finish());
+        let expected: Base64VecU8 =
+            serde_json::from_str(r#""V2agF92rwgSnaHqr46NlAaXG+eDPkDKZ0mbP2h9LpNk=""#).unwrap();
+        assert_eq!(
+            LinkDrop::hash_password(&pk.into(), &bob().into(), "secret"),
+            expected.0
+        );
     }
 
     #[test]
-    #[should_panic(expected = r#"Account already registered"#)]
-    fn test_send_two_times() {
-        let mut contract = LinkDrop::default();
+    fn test_claim_with_password() {
         let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
             .try_into()
             .unwrap();
-        let token_id: TokenId = "0".try_into().unwrap();
-        // Deposit money to linkdrop contract.
-        let deposit = ACCESS_KEY_ALLOWANCE + 1;
         testing_env!(VMContextBuilder::new()
             .current_account_id(linkdrop())
-            .signer_account_id(linkdrop())
-            .attached_deposit(deposit.clone())
             .finish());
-        contract.send(pk.clone(), token_id.clone());
-        assert_eq!(contract.public_key_is_claimable(pk.clone()), true);
+        let mut contract = LinkDrop::init(nft());
+        send_protected(&mut contract, pk.clone());
+        assert!(contract.get_drop(pk.clone()).unwrap().password_protected);
+        claim_protected(&mut contract, pk.clone(), bob(), Some("secret"));
+        assert_eq!(
+            contract.find_drop(&pk.into()).unwrap().status,
+            DropStatus::Claiming
+        );
+        assert!(created_receipts()[0].contains(r#"receiver_id: "bob""#));
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Wrong password"#)]
+    fn test_claim_wrong_password() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
         testing_env!(VMContextBuilder::new()
             .current_account_id(linkdrop())
-            .signer_account_id(linkdrop())
-            .attached_deposit(deposit)
             .finish());
-        contract.send(pk.clone(), token_id);
+        let mut contract = LinkDrop::init(nft());
+        send_protected(&mut contract, pk.clone());
+        claim_protected(&mut contract, pk, bob(), Some("guess"));
     }
 
     #[test]
-    fn test_claim() {
-        let mut contract = LinkDrop::default();
+    #[should_panic(expected = r#"Wrong password"#)]
+    fn test_claim_password_for_another_receiver() {
         let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
             .try_into()
             .unwrap();
-        let token_id: TokenId = "0".try_into().unwrap();
-        // Deposit money to linkdrop contract.
-        let deposit = ACCESS_KEY_ALLOWANCE * 100;
         testing_env!(VMContextBuilder::new()
             .current_account_id(linkdrop())
-            .signer_account_id(linkdrop())
-            .attached_deposit(deposit)
             .finish());
-        contract.send(pk.clone(), token_id);
-        assert_eq!(contract.public_key_is_claimable(pk.clone()), true);
+        let mut contract = LinkDrop::init(nft());
+        send_protected(&mut contract, pk.clone());
+        claim_protected(&mut contract, pk, accounts(2), Some("secret"));
+    }
 
+    #[test]
+    #[should_panic(expected = r#"Password is required"#)]
+    fn test_claim_without_password() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
         testing_env!(VMContextBuilder::new()
             .current_account_id(linkdrop())
-            .predecessor_account_id(linkdrop())
-            .signer_account_pk(pk.clone().into())
-            .attached_deposit(deposit)
             .finish());
-        contract.claim(bob());
+        let mut contract = LinkDrop::init(nft());
+        send_protected(&mut contract, pk.clone());
+        claim_protected(&mut contract, pk, bob(), None);
     }
 
-    #[should_panic(expected = r#"Signer must be eligible to claim the NFT"#)]
     #[test]
-    fn test_claim_pk_not_claimable() {
-        let mut contract = LinkDrop::default();
+    #[should_panic(expected = r#"Password hash must be a sha256 hash"#)]
+    fn test_send_invalid_password_hash() {
         let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
             .try_into()
             .unwrap();
-        // Deposit money to linkdrop contract.
-        let deposit = ACCESS_KEY_ALLOWANCE * 100;
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + 100)
+            .finish());
+        let mut contract = LinkDrop::init(nft());
+        contract.send_bundle(pk, vec![], None, Some(Base64VecU8(b"secret".to_vec())));
+    }
 
+    /// Sends a drop restricted to `rule` and sets up the context of its claim.
+    fn send_restricted(contract: &mut LinkDrop, pk: Base58PublicKey, rule: AllowedReceivers) {
         testing_env!(VMContextBuilder::new()
             .current_account_id(linkdrop())
+            .signer_account_id(linkdrop())
             .predecessor_account_id(linkdrop())
-            .signer_account_pk(pk.clone().into())
-            .attached_deposit(deposit)
+            .attached_deposit(ACCESS_KEY_ALLOWANCE * 2)
+            .finish());
+        contract.send(
+            pk.clone(),
+            nft().try_into().unwrap(),
+            "0".to_string(),
+            None,
+            None,
+            None,
+            None,
+            Some(rule),
+        );
+        resolve_nft_token(contract, owned_token(linkdrop()));
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(pk.into())
+            .attached_deposit(CREATE_SUBACCOUNT_ALLOWANCE + 1)
             .finish());
-        contract.claim(bob());
     }
 
     #[test]
-    fn test_claim_invalid_current_account() {
-        let mut contract = LinkDrop::default();
+    fn test_claim_allowed_receiver() {
         let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
             .try_into()
             .unwrap();
-        let token_id: TokenId = "0".try_into().unwrap();
-        // Deposit money to linkdrop contract.
-        let deposit = ACCESS_KEY_ALLOWANCE * 100;
         testing_env!(VMContextBuilder::new()
             .current_account_id(linkdrop())
-            .signer_account_id(linkdrop())
-            .attached_deposit(deposit)
             .finish());
-        contract.send(pk.clone(), token_id);
-        assert_eq!(contract.public_key_is_claimable(pk.clone()), true);
+        let mut contract = LinkDrop::init(nft());
+        send_restricted(
+            &mut contract,
+            pk.clone(),
+            AllowedReceivers::Accounts(vec!["bob".to_string()]),
+        );
+        assert_eq!(
+            contract.get_drop(pk).unwrap().allowed_receivers,
+            Some(AllowedReceivers::Accounts(vec!["bob".to_string()]))
+        );
+        contract.claim(bob(), None);
+        assert!(created_receipts()[0].contains(&args_part(r#""receiver_id":"bob""#)));
+    }
 
+    #[test]
+    #[should_panic(expected = r#"Account charlie is not allowed to receive the drop"#)]
+    fn test_claim_receiver_not_allowed() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
         testing_env!(VMContextBuilder::new()
-            .current_account_id(bob().try_into().unwrap())
-            .signer_account_pk(pk.clone().into())
-            .attached_deposit(deposit)
+            .current_account_id(linkdrop())
             .finish());
-        contract.claim(bob());
+        let mut contract = LinkDrop::init(nft());
+        send_restricted(
+            &mut contract,
+            pk,
+            AllowedReceivers::Accounts(vec!["bob".to_string()]),
+        );
+        contract.claim(accounts(2), None);
     }
-    #[should_panic(expected = r#"The account ID is invalid"#)]
+
     #[test]
-    fn test_claim_invalid_account() {
-        let mut contract = LinkDrop::default();
+    #[should_panic(expected = r#"Account bob is not allowed to receive the drop"#)]
+    fn test_create_account_and_claim_suffix_not_allowed() {
         let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
             .try_into()
             .unwrap();
-        let token_id: TokenId = "0".try_into().unwrap();
-        // Deposit money to linkdrop contract.
-        let deposit = ACCESS_KEY_ALLOWANCE * 100;
+        let pk2: Base58PublicKey = "2S87aQ1PM9o6eBcEXnTR5yBAVRTiNmvj8J8ngZ6FzSca"
+            .try_into()
+            .unwrap();
         testing_env!(VMContextBuilder::new()
             .current_account_id(linkdrop())
-            .signer_account_id(linkdrop())
-            .attached_deposit(deposit)
             .finish());
-        contract.send(pk.clone(), token_id);
-        assert_eq!(contract.public_key_is_claimable(pk.clone()), true);
+        let mut contract = LinkDrop::init(nft());
+        send_restricted(
+            &mut contract,
+            pk,
+            AllowedReceivers::Suffix(".near".to_string()),
+        );
+        contract.create_account_and_claim(bob(), pk2, None);
+    }
+
+    #[test]
+    fn test_allowed_receivers_suffix() {
+        let rule = AllowedReceivers::Suffix("alice.near".to_string());
+        assert!(rule.allows(&"alice.near".to_string()));
+        assert!(rule.allows(&"nft.alice.near".to_string()));
+        assert!(!rule.allows(&"malice.near".to_string()));
 
+        let rule = AllowedReceivers::Suffix(".near".to_string());
+        assert!(rule.allows(&"bob.near".to_string()));
+        assert!(!rule.allows(&"evilnear".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Allowed receivers must not be empty"#)]
+    fn test_send_no_allowed_receivers() {
+        let pk: Base58PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .try_into()
+            .unwrap();
         testing_env!(VMContextBuilder::new()
             .current_account_id(linkdrop())
-            .signer_account_pk(pk.clone().into())
-            .attached_deposit(deposit)
             .finish());
-        contract.claim("XYZ".to_string().try_into().unwrap());
+        let mut contract = LinkDrop::init(nft());
+        send_restricted(&mut contract, pk, AllowedReceivers::Accounts(vec![]));
     }
 }